@@ -0,0 +1,20 @@
+#![no_main]
+
+use brokefish::Brokefish;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    // the first byte says how much of the rest is key
+    let (key_len, rest) = match data.split_first() {
+        Some((&key_len, rest)) => ((key_len as usize).min(rest.len()), rest),
+        None => return,
+    };
+    let (key, data) = rest.split_at(key_len);
+
+    let bf = Brokefish::new(key);
+    let decrypted = bf.decrypt(data);
+    assert_eq!(decrypted.len(), data.len().div_ceil(8) * 8);
+
+    let round_trip = bf.decrypt(&bf.encrypt(data));
+    assert_eq!(&round_trip[0..data.len()], data);
+});
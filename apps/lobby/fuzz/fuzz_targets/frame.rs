@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use sapphire_protocol::packets::{parse_frame, FrameLimits, PacketHeader, PacketSegmentHeader};
+use std::mem::size_of;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok((header, segments)) = parse_frame(data, &FrameLimits::default()) {
+        assert_eq!(segments.len(), header.count as usize);
+
+        // nothing comes out that wasn't in the frame
+        let total: usize = segments
+            .iter()
+            .map(|s| size_of::<PacketSegmentHeader>() + s.data.len())
+            .sum();
+        assert!(total <= data.len() - size_of::<PacketHeader>());
+    }
+});
@@ -0,0 +1,20 @@
+#![no_main]
+
+use binrw::BinRead;
+use libfuzzer_sys::fuzz_target;
+use lobby::ipc::{IPCClientVersionInfo, IPCReqCharDelete, IPCReqCharList, IPCReqEnterWorld};
+use sapphire_protocol::ipc::split_ipc;
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let (_, body) = match split_ipc(data) {
+        Some(ipc) => ipc,
+        None => return,
+    };
+
+    // whatever the opcode says, none of these should do worse than fail
+    let _ = IPCClientVersionInfo::read(&mut Cursor::new(body));
+    let _ = IPCReqCharList::read(&mut Cursor::new(body));
+    let _ = IPCReqCharDelete::read(&mut Cursor::new(body));
+    let _ = IPCReqEnterWorld::read(&mut Cursor::new(body));
+});
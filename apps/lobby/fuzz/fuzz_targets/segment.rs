@@ -0,0 +1,32 @@
+#![no_main]
+
+use binrw::BinRead;
+use libfuzzer_sys::fuzz_target;
+use sapphire_protocol::{
+    encryption::encryption_init_key,
+    ipc::split_ipc,
+    packets::{parse_frame, FrameLimits, KeepAlive, SegmentType},
+};
+use std::io::Cursor;
+
+fuzz_target!(|data: &[u8]| {
+    let (_, segments) = match parse_frame(data, &FrameLimits::default()) {
+        Ok(frame) => frame,
+        Err(_) => return,
+    };
+
+    for segment in segments {
+        match SegmentType::try_from(segment.segment_header.segment_type) {
+            Ok(SegmentType::KeepAlive | SegmentType::KeepAliveResponse) => {
+                let _ = KeepAlive::read(&mut Cursor::new(&segment.data));
+            }
+            Ok(SegmentType::EncryptionInit) => {
+                let _ = encryption_init_key(&segment.data);
+            }
+            Ok(SegmentType::Ipc) => {
+                let _ = split_ipc(&segment.data);
+            }
+            _ => (),
+        }
+    }
+});
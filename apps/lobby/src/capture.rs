@@ -0,0 +1,194 @@
+use binrw::{BinRead, BinWrite};
+use sapphire_protocol::packets::PacketSegmentHeader;
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const CAPTURE_MAGIC: [u8; 8] = *b"SAPPCAP\0";
+pub const CAPTURE_VERSION: u32 = 1;
+
+pub const DIRECTION_CLIENT_TO_SERVER: u8 = 0;
+pub const DIRECTION_SERVER_TO_CLIENT: u8 = 1;
+
+// a whole frame as it went over the wire, still encrypted
+pub const RECORD_FRAME: u8 = 0;
+// one segment of the frame before it, decrypted
+pub const RECORD_SEGMENT: u8 = 1;
+
+// far bigger than any frame the lobby takes or sends, a longer record is a corrupt file and
+// reading it would mean allocating whatever it claims
+pub const MAX_RECORD_LEN: u32 = 0x100000;
+
+#[derive(BinRead, BinWrite)]
+pub struct CaptureHeader {
+    pub magic: [u8; 8],
+    pub version: u32,
+}
+
+#[derive(BinRead, BinWrite, Debug, Clone)]
+pub struct CaptureRecord {
+    // milliseconds since the unix epoch
+    pub timestamp: u64,
+    // the actor id the lobby gave the connection
+    pub connection_id: u32,
+    pub direction: u8,
+    pub kind: u8,
+    // the segment's place in its frame and its header, all zero for frame records
+    pub segment_index: u16,
+    pub segment_type: u16,
+    pub source_actor: u32,
+    pub target_actor: u32,
+    #[br(assert(len <= MAX_RECORD_LEN))]
+    pub len: u32,
+    #[br(count = len)]
+    pub data: Vec<u8>,
+}
+
+impl CaptureRecord {
+    pub fn is_from_client(&self) -> bool {
+        self.direction == DIRECTION_CLIENT_TO_SERVER
+    }
+
+    pub fn is_frame(&self) -> bool {
+        self.kind == RECORD_FRAME
+    }
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_millis() as u64
+}
+
+// writes everything one connection sends and receives to <dir>/<connection id>.cap.
+// nothing here is worth dropping a client over, so a failed write just stops the recording
+pub struct CaptureRecorder {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    connection_id: u32,
+    // counts the segments recorded since each direction's last frame
+    segment_index: [u16; 2],
+}
+
+impl CaptureRecorder {
+    pub fn create(dir: &str, connection_id: u32) -> Result<CaptureRecorder, Box<dyn Error>> {
+        fs::create_dir_all(dir)?;
+        let path = Path::new(dir).join(format!("{:08x}.cap", connection_id));
+        let mut writer = BufWriter::new(File::create(&path)?);
+
+        CaptureHeader {
+            magic: CAPTURE_MAGIC,
+            version: CAPTURE_VERSION,
+        }
+        .write_to(&mut writer)?;
+        writer.flush()?;
+
+        Ok(CaptureRecorder {
+            path,
+            writer: Some(writer),
+            connection_id,
+            segment_index: [0; 2],
+        })
+    }
+
+    pub fn frame(&mut self, direction: u8, frame: &[u8]) {
+        self.segment_index[direction as usize] = 0;
+        self.write(CaptureRecord {
+            timestamp: now_millis(),
+            connection_id: self.connection_id,
+            direction,
+            kind: RECORD_FRAME,
+            segment_index: 0,
+            segment_type: 0,
+            source_actor: 0,
+            target_actor: 0,
+            len: frame.len() as u32,
+            data: frame.to_vec(),
+        });
+    }
+
+    pub fn segment(&mut self, direction: u8, header: &PacketSegmentHeader, data: &[u8]) {
+        let segment_index = self.segment_index[direction as usize];
+        self.segment_index[direction as usize] += 1;
+
+        self.write(CaptureRecord {
+            timestamp: now_millis(),
+            connection_id: self.connection_id,
+            direction,
+            kind: RECORD_SEGMENT,
+            segment_index,
+            segment_type: header.segment_type,
+            source_actor: header.source_actor,
+            target_actor: header.target_actor,
+            len: data.len() as u32,
+            data: data.to_vec(),
+        });
+    }
+
+    fn write(&mut self, record: CaptureRecord) {
+        let writer = match &mut self.writer {
+            Some(writer) => writer,
+            None => return,
+        };
+
+        // flushed every time so a crash doesn't eat the end of the capture
+        let result = record
+            .write_to(writer)
+            .map_err(|e| e.to_string())
+            .and_then(|_| writer.flush().map_err(|e| e.to_string()));
+
+        if let Err(e) = result {
+            println!(
+                "failed to write to capture {}, stopping: {}",
+                self.path.display(),
+                e
+            );
+            self.writer = None;
+        }
+    }
+}
+
+// reads a capture back one record at a time
+pub struct CaptureReader {
+    reader: BufReader<File>,
+}
+
+impl CaptureReader {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<CaptureReader, Box<dyn Error>> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let header = CaptureHeader::read(&mut reader)?;
+        if header.magic != CAPTURE_MAGIC {
+            return Err("not a capture file".into());
+        }
+        if header.version != CAPTURE_VERSION {
+            return Err(format!("unsupported capture version {}", header.version).into());
+        }
+
+        Ok(CaptureReader { reader })
+    }
+}
+
+impl Iterator for CaptureReader {
+    type Item = Result<CaptureRecord, Box<dyn Error>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // the file ends between records, anything else is a capture a crash cut short
+        match self.reader.fill_buf() {
+            Ok([]) => return None,
+            Ok(_) => (),
+            Err(e) => return Some(Err(e.into())),
+        }
+
+        Some(CaptureRecord::read(&mut self.reader).map_err(|e| e.into()))
+    }
+}
+
+pub fn read_capture<P: AsRef<Path>>(path: P) -> Result<Vec<CaptureRecord>, Box<dyn Error>> {
+    CaptureReader::open(path)?.collect()
+}
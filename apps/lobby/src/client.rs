@@ -2,73 +2,263 @@ use std::{
     error::Error,
     io::Cursor,
     mem::size_of,
-    time::{SystemTime, UNIX_EPOCH},
+    sync::Arc,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    net::TcpStream,
+    io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt},
+    time,
 };
 
 use crate::{
-    ipc::{ClientLobbyIpcType, IPCHeader, IPCServiceAccount, IPCServiceIDInfo},
-    packets::{PacketHeader, PacketRaw, PacketSegmentHeader, SegmentType},
+    capture::{CaptureRecorder, DIRECTION_CLIENT_TO_SERVER, DIRECTION_SERVER_TO_CLIENT},
+    config::Config,
+    connections::Connections,
+    ipc::{
+        IPCCharCreate, IPCCharList, IPCCharacter, IPCClientVersionInfo, IPCEnterWorld,
+        IPCLobbyError, IPCReqCharDelete, IPCReqCharList, IPCReqEnterWorld, IPCRetainer,
+        IPCRetainerList, IPCServer, IPCServerList, IPCServiceAccount, IPCServiceIDInfo,
+        CHAR_CREATE_TYPE_DELETE, MAX_CHARACTERS, MAX_RETAINERS, MAX_SERVERS, MAX_SERVICE_ACCOUNTS,
+    },
+    lobby_error::LobbyError,
+    worlds::WorldRegistry,
 };
 use binrw::{BinRead, BinWrite};
-use brokefish::Brokefish;
+use sapphire_protocol::{
+    encryption::{decrypt_ipc, derive_key, encrypt_ipc, encryption_init_key},
+    fixed_str::FixedStr,
+    ipc::{split_ipc, Direction, IPCHeader, IpcPacket},
+    opcodes::OpcodeTable,
+    packets::{
+        frame_size, parse_frame, write_frame, FrameLimits, KeepAlive, PacketRaw,
+        PacketSegmentHeader, SegmentType,
+    },
+};
+use storage::{Character, Storage};
+
+// what the character list tells the client about the account, until we track it per account
+const MAX_CHARACTERS_ON_WORLD: u16 = 8;
+// 4 is Endwalker
+const ENTITLED_EXPANSION: u32 = 4;
+
+// a ReqEnterWorld we couldn't let through yet
+struct QueuedLogin {
+    ticket: u64,
+    seq: u64,
+    character: Character,
+}
 
-pub struct Client {
-    pub stream: TcpStream,
+// anything that reads and writes bytes will do, a TcpStream in the server and an in-memory
+// duplex in tests
+pub struct Client<S> {
+    pub stream: S,
     pub encryption_key: Option<Vec<u8>>,
+    pub game_version: Option<u16>,
+    pub db: Arc<dyn Storage>,
+    pub config: Arc<Config>,
+    pub worlds: WorldRegistry,
+    // set once the client has shown us a valid session
+    pub account_id: Option<u32>,
+    pub session_id: Option<String>,
+    queued: Option<QueuedLogin>,
+    // one key per allowed game version until the first IPC tells us which one the client uses
+    key_candidates: Vec<(u16, Vec<u8>)>,
+    // the key and key phrase from the EncryptionInit, for finding the key of a game version we
+    // don't allow so we can tell the client to update
+    encryption_init: Option<(Vec<u8>, Vec<u8>)>,
+    // who our segments say they're from and to, a fresh one per connection
+    actor_id: u32,
+    connections: Connections,
+    // our keepalive timestamps count from here
+    connected_at: Instant,
+    last_received: Instant,
+    // the id of the last keepalive we sent, answers to older ones are ignored
+    keepalive_id: u32,
+    capture: Option<CaptureRecorder>,
 }
 
-impl Client {
-    pub async fn handle(&mut self) {
-        let mut buf: Vec<u8> = vec![0; 2048];
+// tries every game version we don't allow, nearest the ones we do first since that's where an
+// out of date (or too new) client will be, until one decrypts `data` into a ClientVersionInfo
+fn guess_game_version(
+    key: &[u8],
+    key_phrase: &[u8],
+    configured: &[u16],
+    opcodes: &[u16],
+    data: &[u8],
+) -> Option<(u16, Vec<u8>)> {
+    let mut game_versions: Vec<u16> = (0..=u16::MAX).filter(|v| !configured.contains(v)).collect();
+    game_versions.sort_by_key(|v| configured.iter().map(|c| c.abs_diff(*v)).min());
+
+    // the opcode alone would turn up a wrong key every 65536 or so, the zeroed parts of the
+    // header make that vanishingly rare. only the header needs decrypting, and 8 bytes past it
+    // so it isn't the plain tail
+    let header = data.get(0..size_of::<IPCHeader>() + 8)?;
+    game_versions.into_iter().find_map(|game_version| {
+        let key = derive_key(key, key_phrase, game_version);
+        let header = decrypt_ipc(&key, Direction::ClientToServer, header);
+        let (ipc_type, _) = split_ipc(&header)?;
+        let zeroed = header[4..6] == [0; 2] && header[12..16] == [0; 4];
+        (opcodes.contains(&ipc_type) && zeroed).then_some((game_version, key))
+    })
+}
 
-        loop {
-            let n = self
-                .stream
-                .read(&mut buf)
-                .await
-                .expect("failed to read data from socket");
+// list IPCs number their packets in the upper six bits of a u8, and the client wants at least
+// one (possibly empty) packet so it knows the list is over
+fn packet_chunks<T>(items: &[T], per_packet: usize) -> Result<Vec<&[T]>, Box<dyn Error>> {
+    if items.is_empty() {
+        return Ok(vec![&[]]);
+    }
+
+    let chunks: Vec<&[T]> = items.chunks(per_packet).collect();
+    if chunks.len() > 0x40 {
+        return Err(format!("too many list entries ({})", items.len()).into());
+    }
+
+    Ok(chunks)
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> Client<S> {
+    pub fn new(
+        stream: S,
+        db: Arc<dyn Storage>,
+        config: Arc<Config>,
+        worlds: WorldRegistry,
+        connections: Connections,
+    ) -> Client<S> {
+        Client {
+            stream,
+            encryption_key: None,
+            game_version: None,
+            db,
+            config,
+            worlds,
+            account_id: None,
+            session_id: None,
+            queued: None,
+            key_candidates: Vec::new(),
+            encryption_init: None,
+            actor_id: 0,
+            connections,
+            connected_at: Instant::now(),
+            last_received: Instant::now(),
+            keepalive_id: 0,
+            capture: None,
+        }
+    }
 
-            if n == 0 {
+    pub async fn handle(&mut self) {
+        self.actor_id = match self.db.allocate_lobby_actor_id() {
+            Ok(id) => id,
+            Err(e) => {
+                println!("failed to allocate an actor id, closing connection: {}", e);
                 return;
             }
+        };
 
-            self.handle_packets(&buf[0..n])
-                .await
-                .expect("could not handle packet");
+        if let Some(dir) = &self.config.capture_dir {
+            match CaptureRecorder::create(dir, self.actor_id) {
+                Ok(capture) => self.capture = Some(capture),
+                Err(e) => println!("failed to start capture: {}", e),
+            }
         }
-    }
 
-    async fn handle_packets(&mut self, buf: &[u8]) -> Result<(), Box<dyn Error>> {
-        println!("recv packet: {:02X?}", buf);
-        let mut cursor = Cursor::new(buf);
+        self.connections.open(self.actor_id);
+        self.handle_connection().await;
+        self.connections.close(self.actor_id);
 
-        let header = PacketHeader::read(&mut cursor).expect("could not parse packet header");
-        println!("{:#?}", header);
+        // don't hold up everyone behind us until the ticket goes stale
+        if let Some(queued) = self.queued.take() {
+            if let Err(e) = self.db.leave_queue(queued.ticket) {
+                println!("failed to leave the login queue: {}", e);
+            }
+        }
+    }
 
-        for _ in 0..header.count {
-            let segment_header = PacketSegmentHeader::read(&mut cursor)
-                .expect("could not parse packet segment header");
-            println!("{:#?}", segment_header);
+    async fn handle_connection(&mut self) {
+        let mut buf: Vec<u8> = vec![0; 2048];
+        // frames don't line up with reads, so whatever's arrived of the next one waits here
+        let mut pending: Vec<u8> = Vec::new();
+        let limits = self.config.frame_limits();
+        let mut queue_timer =
+            time::interval(Duration::from_secs(self.config.queue_update_interval));
+        let keepalive_interval = Duration::from_secs(self.config.keepalive_interval);
+        let mut keepalive_timer = time::interval_at(
+            time::Instant::now() + keepalive_interval,
+            keepalive_interval,
+        );
 
-            let data_size =
-                (segment_header.size - (size_of::<PacketSegmentHeader>() as u32)) as usize;
-            let mut data: Vec<u8> = vec![0; data_size];
-            cursor
-                .read_exact(&mut data)
-                .await
-                .expect("could not read data");
+        loop {
+            tokio::select! {
+                read = self.stream.read(&mut buf) => {
+                    let n = match read {
+                        Ok(n) => n,
+                        Err(e) => {
+                            println!("failed to read data from socket: {}", e);
+                            return;
+                        }
+                    };
+
+                    if n == 0 {
+                        return;
+                    }
+                    self.last_received = Instant::now();
+                    pending.extend_from_slice(&buf[0..n]);
 
-            println!("packet data: {:02X?}", data);
+                    if let Err(e) = self.handle_frames(&mut pending, &limits).await {
+                        println!("closing connection: {}", e);
+                        return;
+                    }
+                }
+                _ = queue_timer.tick(), if self.queued.is_some() => {
+                    if let Err(e) = self.update_queue().await {
+                        println!("closing connection: {}", e);
+                        return;
+                    }
+                }
+                _ = keepalive_timer.tick() => {
+                    if let Err(e) = self.send_keepalive().await {
+                        println!("closing connection: {}", e);
+                        return;
+                    }
+                }
+            }
+        }
+    }
 
-            let packet = PacketRaw {
-                segment_header,
-                data,
+    // handles every whole frame at the start of pending, leaving the rest for the next read
+    async fn handle_frames(
+        &mut self,
+        pending: &mut Vec<u8>,
+        limits: &FrameLimits,
+    ) -> Result<(), Box<dyn Error>> {
+        loop {
+            let size = match frame_size(pending, limits)? {
+                Some(size) if pending.len() >= size => size,
+                _ => break,
             };
 
+            let frame: Vec<u8> = pending.drain(0..size).collect();
+            self.handle_packets(&frame, limits).await?;
+        }
+        Ok(())
+    }
+
+    async fn handle_packets(
+        &mut self,
+        buf: &[u8],
+        limits: &FrameLimits,
+    ) -> Result<(), Box<dyn Error>> {
+        println!("recv packet: {:02X?}", buf);
+        if let Some(capture) = &mut self.capture {
+            capture.frame(DIRECTION_CLIENT_TO_SERVER, buf);
+        }
+        let (header, segments) = parse_frame(buf, limits)?;
+        println!("{:#?}", header);
+
+        for packet in segments {
+            println!("{:#?}", packet.segment_header);
+            println!("packet data: {:02X?}", packet.data);
+
             self.handle_packet(packet).await?;
         }
         Ok(())
@@ -76,41 +266,58 @@ impl Client {
 
     async fn handle_packet(&mut self, packet: PacketRaw) -> Result<(), Box<dyn Error>> {
         // todo: store this enum in the struct
-        let segment_type: SegmentType = SegmentType::try_from(packet.segment_header.segment_type)
-            .expect("couldn't determine segment type");
+        let segment_type = match SegmentType::try_from(packet.segment_header.segment_type) {
+            Ok(segment_type) => segment_type,
+            Err(_) => {
+                println!(
+                    "Unknown segment type {}",
+                    packet.segment_header.segment_type
+                );
+                return Ok(());
+            }
+        };
+
+        // IPCs are recorded once they're decrypted
+        if segment_type != SegmentType::Ipc {
+            self.capture_segment(&packet.segment_header, &packet.data);
+        }
 
         match segment_type {
             SegmentType::KeepAlive => {
-                let id = &packet.data[0..4];
-                let timestamp = &packet.data[4..8];
-                let segment_header = PacketSegmentHeader::new(0x08, 0x08, 0, 0);
-
-                let mut data: [u8; 8] = [0; 8];
-                data[0..4].copy_from_slice(id);
-                data[4..8].copy_from_slice(timestamp);
-
-                self.send_packet(segment_header, &data)
-                    .await
-                    .expect("failed to response to keepalive");
+                let keepalive = KeepAlive::read(&mut Cursor::new(&packet.data))?;
+                let mut data = Cursor::new(Vec::new());
+                keepalive
+                    .write_to(&mut data)
+                    .expect("failed to write keepalive");
+
+                let segment_header = PacketSegmentHeader::new(
+                    SegmentType::KeepAliveResponse as u16,
+                    data.get_ref().len() as u32,
+                    0,
+                    0,
+                );
+                self.send_packet(segment_header, data.get_ref()).await?;
+            }
+            SegmentType::KeepAliveResponse => {
+                let keepalive = KeepAlive::read(&mut Cursor::new(&packet.data))?;
+                if keepalive.id == self.keepalive_id {
+                    let sent = Duration::from_millis(keepalive.timestamp as u64);
+                    let latency = self.connected_at.elapsed().saturating_sub(sent);
+                    self.connections
+                        .update(self.actor_id, |stats| stats.latency = Some(latency));
+                }
             }
             SegmentType::EncryptionInit => {
-                let key = &packet.data[100..104];
-                let key_phrase = &packet.data[36..68];
-
-                let mut base_key: [u8; 0x2c] = [0; 0x2c];
-                base_key[0] = 0x78;
-                base_key[1] = 0x56;
-                base_key[2] = 0x34;
-                base_key[3] = 0x12;
-                base_key[4..8].copy_from_slice(key);
+                let (key, key_phrase) =
+                    encryption_init_key(&packet.data).ok_or("EncryptionInit too short")?;
 
-                // the game ver (0xD417 = 6100)
-                base_key[8] = 0xd4;
-                base_key[9] = 0x17;
-                base_key[12..44].copy_from_slice(key_phrase);
-
-                let digest = md5::compute(base_key).to_vec();
-                self.encryption_key = Some(digest);
+                self.key_candidates = self
+                    .config
+                    .client_versions
+                    .iter()
+                    .map(|v| (v.game_version, derive_key(key, key_phrase, v.game_version)))
+                    .collect();
+                self.encryption_init = Some((key.to_vec(), key_phrase.to_vec()));
 
                 let mut send_data: [u8; 0x290] = [0; 0x290];
                 send_data[0..4].copy_from_slice(&(0xe0003c2a_u32).to_le_bytes());
@@ -119,30 +326,44 @@ impl Client {
                     .await?;
             }
             SegmentType::Ipc => {
+                if self.encryption_key.is_none()
+                    && !self.key_candidates.is_empty()
+                    && !self.negotiate_game_version(&packet.data)
+                {
+                    return self.reject_game_version(packet).await;
+                }
+
                 let data = {
                     if let Some(enc_key) = &self.encryption_key {
-                        let enc_data = &packet.data[0..packet.data.len() - 0x08];
-                        let bf = Brokefish::new(enc_key);
-                        let decrypted = bf.decrypt(enc_data);
+                        let decrypted =
+                            decrypt_ipc(enc_key, Direction::ClientToServer, &packet.data);
                         println!("decrypted: {:02X?}", decrypted);
-
-                        let mut data: Vec<u8> = vec![0; packet.data.len()];
-                        data[0..packet.data.len() - 0x08].copy_from_slice(&decrypted);
-
-                        data
+                        decrypted
                     } else {
                         packet.data
                     }
                 };
+                self.capture_segment(&packet.segment_header, &data);
+
+                // every lobby request starts with the sequence number we have to answer with
+                let seq = data
+                    .get(size_of::<IPCHeader>()..size_of::<IPCHeader>() + 8)
+                    .map(|seq| u64::from_le_bytes(seq.try_into().unwrap()))
+                    .unwrap_or(0);
+
+                let rejection = match self.handle_ipc(&data).await {
+                    Ok(()) => None,
+                    Err(e) => match e.downcast_ref::<LobbyError>() {
+                        Some(error) => Some(*error),
+                        None => {
+                            println!("failed to handle IPC: {}", e);
+                            Some(LobbyError::Internal)
+                        }
+                    },
+                };
 
-                let ipc_type_num =
-                    u16::from_le_bytes(data[2..4].try_into().expect("couldn't determine IPC type"));
-                let ipc_type = ClientLobbyIpcType::try_from(ipc_type_num)
-                    .expect("couldn't determine IPC type");
-
-                match ipc_type {
-                    ClientLobbyIpcType::ClientVersionInfo => self.send_service_account().await?,
-                    _ => println!("Unknown IPC type {}", ipc_type_num),
+                if let Some(error) = rejection {
+                    self.send_error(seq, error).await?;
                 }
             }
             _ => (),
@@ -151,41 +372,565 @@ impl Client {
         Ok(())
     }
 
-    async fn send_service_account(&mut self) -> Result<(), Box<dyn Error>> {
-        let mut service_id_info = IPCServiceIDInfo::default();
-        let mut name_buf: [u8; 0x44] = [0; 0x44];
-        let name = "FINAL FANTASY XIV".as_bytes();
-        name_buf[0..name.len()].copy_from_slice(name);
-
-        let service_account = IPCServiceAccount {
-            id: 0,
-            unknown: 0,
-            index: 0,
-            name: name_buf,
+    fn capture_segment(&mut self, header: &PacketSegmentHeader, data: &[u8]) {
+        if let Some(capture) = &mut self.capture {
+            capture.segment(DIRECTION_CLIENT_TO_SERVER, header, data);
+        }
+    }
+
+    // handlers turn a request down by returning a LobbyError, which gets sent to the client
+    async fn handle_ipc(&mut self, data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let (ipc_type_num, body) = split_ipc(data).ok_or("IPC shorter than its header")?;
+        let ipc_type = match self
+            .opcodes()?
+            .name(Direction::ClientToServer, ipc_type_num)
+        {
+            Some(ipc_type) => ipc_type,
+            None => {
+                println!("Unknown IPC type {}", ipc_type_num);
+                return Ok(());
+            }
+        };
+
+        match ipc_type {
+            IPCClientVersionInfo::NAME => {
+                let mut cursor = Cursor::new(body);
+                let version_info = IPCClientVersionInfo::read(&mut cursor)?;
+                self.handle_client_version_info(version_info).await
+            }
+            IPCReqCharList::NAME => {
+                let mut cursor = Cursor::new(body);
+                let req = IPCReqCharList::read(&mut cursor)?;
+                self.handle_req_char_list(req).await
+            }
+            IPCReqCharDelete::NAME => {
+                let mut cursor = Cursor::new(body);
+                let req = IPCReqCharDelete::read(&mut cursor)?;
+                self.handle_req_char_delete(req).await
+            }
+            IPCReqEnterWorld::NAME => {
+                let mut cursor = Cursor::new(body);
+                let req = IPCReqEnterWorld::read(&mut cursor)?;
+                self.handle_req_enter_world(req).await
+            }
+            _ => {
+                println!("Unhandled IPC type {}", ipc_type_num);
+                Ok(())
+            }
+        }
+    }
+
+    // the client mixes its game version into the encryption key, so whichever candidate key
+    // turns its first IPC into a ClientVersionInfo tells us which version it's running
+    fn negotiate_game_version(&mut self, data: &[u8]) -> bool {
+        let tables = &self.config.opcode_tables;
+        let found = self.key_candidates.iter().find(|(game_version, key)| {
+            let expected = tables
+                .get(*game_version)
+                .and_then(|table| table.opcode_of::<IPCClientVersionInfo>());
+            expected.is_some()
+                && split_ipc(&decrypt_ipc(key, Direction::ClientToServer, data))
+                    .map(|(ipc_type, _)| ipc_type)
+                    == expected
+        });
+
+        match found {
+            Some((game_version, key)) => {
+                self.game_version = Some(*game_version);
+                self.encryption_key = Some(key.clone());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // the client's game version isn't one we allow. if we can find out which it is, we can
+    // answer in its key and tell it to update instead of leaving it hanging
+    async fn reject_game_version(&mut self, packet: PacketRaw) -> Result<(), Box<dyn Error>> {
+        let tables = &self.config.opcode_tables;
+        let configured: Vec<u16> = self.key_candidates.iter().map(|(v, _)| *v).collect();
+        let opcodes: Vec<u16> = configured
+            .iter()
+            .filter_map(|v| tables.get(*v)?.opcode_of::<IPCClientVersionInfo>())
+            .collect();
+        let (key, key_phrase) = self
+            .encryption_init
+            .clone()
+            .ok_or("IPC before EncryptionInit")?;
+
+        // up to every game version there is, so off the async threads
+        let data = packet.data.clone();
+        let found = tokio::task::spawn_blocking(move || {
+            guess_game_version(&key, &key_phrase, &configured, &opcodes, &data)
+        })
+        .await?;
+
+        let (game_version, key) = match found {
+            Some(found) => found,
+            None => {
+                // not even a game version we don't allow decrypts it, so the client couldn't
+                // read anything we sent back. all we can do is hang up and say why
+                self.capture_segment(&packet.segment_header, &packet.data);
+                return Err("no game version decrypts the client's first IPC".into());
+            }
+        };
+
+        let data = decrypt_ipc(&key, Direction::ClientToServer, &packet.data);
+        self.capture_segment(&packet.segment_header, &data);
+        let seq = data
+            .get(size_of::<IPCHeader>()..size_of::<IPCHeader>() + 8)
+            .map(|seq| u64::from_le_bytes(seq.try_into().unwrap()))
+            .unwrap_or(0);
+
+        // the error goes out with our preferred version's opcode, there's nothing better to use
+        println!("rejecting game version {}", game_version);
+        self.encryption_key = Some(key);
+        self.send_error(seq, LobbyError::ClientOutOfDate).await
+    }
+
+    async fn handle_client_version_info(
+        &mut self,
+        version_info: IPCClientVersionInfo,
+    ) -> Result<(), Box<dyn Error>> {
+        let version = version_info.version.to_string_lossy();
+        let allowed = match self.game_version {
+            Some(game_version) => self.config.is_version_allowed(game_version, &version),
+            None => false,
         };
 
-        service_id_info.add_service_account(service_account);
+        if !allowed {
+            println!(
+                "rejecting client version {} (game version {:?})",
+                version, self.game_version
+            );
+            return Err(LobbyError::ClientOutOfDate.into());
+        }
+
+        let session_id = version_info
+            .session_id
+            .as_str()
+            .map_err(|_| LobbyError::InvalidSession)?
+            .to_string();
+        let account_id = self
+            .db
+            .account_for_session(&session_id)?
+            .ok_or(LobbyError::InvalidSession)?;
+        self.account_id = Some(account_id);
+        self.session_id = Some(session_id);
+        self.connections
+            .update(self.actor_id, |stats| stats.account_id = Some(account_id));
+
+        self.send_service_account(account_id, version_info.seq)
+            .await
+    }
+
+    async fn send_service_account(
+        &mut self,
+        account_id: u32,
+        seq: u64,
+    ) -> Result<(), Box<dyn Error>> {
+        let mut service_accounts = Vec::new();
+
+        for account in self.db.service_accounts(account_id)? {
+            if !account.enabled {
+                continue;
+            }
+
+            service_accounts.push(IPCServiceAccount {
+                id: account.id,
+                name: FixedStr::truncated(&account.name),
+                ..Default::default()
+            });
+        }
+
+        if service_accounts.is_empty() {
+            return Err(LobbyError::NoServiceAccounts.into());
+        }
+
+        let chunks = packet_chunks(&service_accounts, MAX_SERVICE_ACCOUNTS)?;
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut service_id_info = IPCServiceIDInfo::new(seq, i as u8, i == chunks.len() - 1);
+            for service_account in chunk.iter() {
+                service_id_info.add_service_account(service_account.clone())?;
+            }
+
+            let mut writer = Cursor::new(Vec::new());
+            service_id_info
+                .write_to(&mut writer)
+                .expect("failed to write service ID info");
+
+            println!("sending service accounts ({}/{})", i + 1, chunks.len());
+            self.send_ipc_packet::<IPCServiceIDInfo>(writer.get_ref())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn handle_req_char_list(&mut self, req: IPCReqCharList) -> Result<(), Box<dyn Error>> {
+        let account_id = self.account_id.ok_or(LobbyError::InvalidSession)?;
+
+        self.send_world_list(req.seq).await?;
+        self.send_char_list(req.seq, account_id).await?;
+        self.send_retainer_list(req.seq, account_id).await
+    }
+
+    async fn handle_req_char_delete(
+        &mut self,
+        req: IPCReqCharDelete,
+    ) -> Result<(), Box<dyn Error>> {
+        let account_id = self.account_id.ok_or(LobbyError::InvalidSession)?;
+        let character = self
+            .db
+            .characters(account_id)?
+            .into_iter()
+            .find(|c| c.content_id == req.content_id && req.name == c.name.as_str())
+            .ok_or(LobbyError::CharacterNotFound)?;
+
+        // retainers have to be dismissed first, or their items would go with the character
+        if self.db.retainer_count(character.id)? > 0 {
+            return Err(LobbyError::CharacterHasRetainers.into());
+        }
+
+        self.db.delete_character(character.id)?;
+        println!(
+            "deleted character {} ({})",
+            character.name, character.content_id
+        );
+
+        let world_name = self.world_name(character.world_id);
+        let mut reply = IPCCharCreate::new(req.seq, CHAR_CREATE_TYPE_DELETE, character.content_id);
+        reply.name = FixedStr::truncated(&character.name);
+        reply.world_name = world_name.clone();
+        reply.current_world_name = world_name;
+
+        let mut writer = Cursor::new(Vec::new());
+        reply
+            .write_to(&mut writer)
+            .expect("failed to write character deletion");
+
+        self.send_ipc_packet::<IPCCharCreate>(writer.get_ref())
+            .await
+    }
+
+    async fn handle_req_enter_world(
+        &mut self,
+        req: IPCReqEnterWorld,
+    ) -> Result<(), Box<dyn Error>> {
+        let account_id = self.account_id.ok_or(LobbyError::InvalidSession)?;
+        if self.queued.is_some() {
+            return Ok(());
+        }
+
+        let character = self
+            .db
+            .characters(account_id)?
+            .into_iter()
+            .find(|c| c.content_id == req.content_id)
+            .ok_or(LobbyError::CharacterNotFound)?;
+
+        let world = self
+            .worlds
+            .world(character.world_id)
+            .ok_or(LobbyError::WorldUnavailable)?;
+        if world.status.maintenance {
+            return Err(LobbyError::Maintenance.into());
+        }
+
+        // anyone already waiting goes first, even if a slot just opened up
+        if self.db.queue_len(world.id)? == 0 && self.worlds.has_free_slot(world.id) {
+            return self.enter_world(req.seq, &character).await;
+        }
+
+        let ticket = self.db.join_queue(world.id, character.content_id)?;
+        println!(
+            "{} is queued for {} (ticket {})",
+            character.name, world.name, ticket
+        );
+
+        self.queued = Some(QueuedLogin {
+            ticket,
+            seq: req.seq,
+            character,
+        });
+        self.update_queue().await
+    }
+
+    // runs every queue_update_interval while queued: either lets us in or tells the client
+    // where it stands
+    async fn update_queue(&mut self) -> Result<(), Box<dyn Error>> {
+        let queued = match &self.queued {
+            Some(queued) => queued,
+            None => return Ok(()),
+        };
+
+        let ticket = queued.ticket;
+        let seq = queued.seq;
+        let world_id = queued.character.world_id;
+        let stale_after = self.config.queue_update_interval * 3;
+
+        let position = match self.db.queue_position(ticket, stale_after)? {
+            Some(position) => position,
+            None => {
+                // an admin cleared the queue
+                self.queued = None;
+                return self.send_error(seq, LobbyError::WorldFull).await;
+            }
+        };
+
+        if position == 0 && self.worlds.has_free_slot(world_id) {
+            self.db.leave_queue(ticket)?;
+            self.db.record_admission(world_id)?;
+
+            let queued = self.queued.take().expect("queued login disappeared");
+            return self.enter_world(queued.seq, &queued.character).await;
+        }
+
+        let per_place = self
+            .db
+            .admission_interval(world_id)?
+            .unwrap_or(self.config.queue_default_wait);
+        let wait_minutes = ((position as u64 + 1) * per_place).div_ceil(60);
+
+        let mut status = IPCLobbyError::new(seq, LobbyError::InQueue);
+        status.param = position + 1;
+        status.message = FixedStr::truncated(&wait_minutes.to_string());
+
+        let mut writer = Cursor::new(Vec::new());
+        status
+            .write_to(&mut writer)
+            .expect("failed to write queue status");
+
+        println!(
+            "queue position {} for ticket {}, about {} minutes",
+            position + 1,
+            ticket,
+            wait_minutes
+        );
+        self.send_ipc_packet::<IPCLobbyError>(writer.get_ref())
+            .await
+    }
+
+    async fn enter_world(&mut self, seq: u64, character: &Character) -> Result<(), Box<dyn Error>> {
+        let world = self
+            .config
+            .worlds
+            .iter()
+            .find(|world| world.id == character.world_id)
+            .ok_or(LobbyError::WorldUnavailable)?;
+
+        let mut enter_world = IPCEnterWorld::new(seq, character.character_id, character.content_id);
+        enter_world.port = world.port;
+        // Config::load made sure the host fits, and cut short the session id would be refused
+        enter_world.host = FixedStr::new(&world.host)?;
+        enter_world.session_id = FixedStr::new(self.session_id.as_deref().unwrap_or_default())?;
+
+        self.worlds.record_handoff(character.world_id);
+        println!("sending {} to {}", character.name, world.name);
 
         let mut writer = Cursor::new(Vec::new());
-        service_id_info
+        enter_world
             .write_to(&mut writer)
-            .expect("failed to write service ID info");
+            .expect("failed to write enter world");
 
-        println!("sending service account");
-        self.send_ipc_packet(12, writer.get_ref()).await?;
+        self.send_ipc_packet::<IPCEnterWorld>(writer.get_ref())
+            .await
+    }
+
+    // any configured world, not just the ones in our data centre
+    fn world_name(&self, world_id: u16) -> FixedStr<0x20> {
+        self.config
+            .worlds
+            .iter()
+            .find(|world| world.id == world_id)
+            .map(|world| FixedStr::truncated(&world.name))
+            .unwrap_or_default()
+    }
+
+    async fn send_char_list(&mut self, seq: u64, account_id: u32) -> Result<(), Box<dyn Error>> {
+        let characters = self.db.characters(account_id)?;
+        let chunks = packet_chunks(&characters, MAX_CHARACTERS)?;
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let is_last = i == chunks.len() - 1;
+            let mut char_list = IPCCharList::new(seq, i as u8, is_last);
+
+            if is_last {
+                char_list.max_characters_on_world = MAX_CHARACTERS_ON_WORLD;
+                char_list.entitled_expansion = ENTITLED_EXPANSION;
+            }
+
+            for character in chunk.iter() {
+                let world_name = self.world_name(character.world_id);
+                let entry = IPCCharacter {
+                    id: character.character_id,
+                    content_id: character.content_id,
+                    world_id: character.world_id,
+                    current_world_id: character.world_id,
+                    name: FixedStr::truncated(&character.name),
+                    world_name: world_name.clone(),
+                    current_world_name: world_name,
+                    // storage won't keep one that doesn't fit
+                    detail_json: FixedStr::new(&character.detail_json)?,
+                    ..Default::default()
+                };
+
+                char_list.add_character(entry)?;
+            }
+
+            let mut writer = Cursor::new(Vec::new());
+            char_list
+                .write_to(&mut writer)
+                .expect("failed to write character list");
+
+            self.send_ipc_packet::<IPCCharList>(writer.get_ref())
+                .await?;
+        }
 
         Ok(())
     }
 
-    async fn send_ipc_packet(
+    async fn send_retainer_list(
+        &mut self,
+        seq: u64,
+        account_id: u32,
+    ) -> Result<(), Box<dyn Error>> {
+        let retainers = self.db.retainers(account_id)?;
+        let chunks = packet_chunks(&retainers, MAX_RETAINERS)?;
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let mut retainer_list = IPCRetainerList::new(seq, i as u8, i == chunks.len() - 1);
+
+            for retainer in chunk.iter() {
+                let entry = IPCRetainer {
+                    id: retainer.id as u64,
+                    owner_content_id: retainer.owner_content_id,
+                    class_job: retainer.class_job,
+                    level: retainer.level,
+                    name: FixedStr::truncated(&retainer.name),
+                };
+
+                retainer_list.add_retainer(entry)?;
+            }
+
+            let mut writer = Cursor::new(Vec::new());
+            retainer_list
+                .write_to(&mut writer)
+                .expect("failed to write retainer list");
+
+            self.send_ipc_packet::<IPCRetainerList>(writer.get_ref())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    // statuses come from the world servers' latest heartbeats, so this is always current
+    async fn send_world_list(&mut self, seq: u64) -> Result<(), Box<dyn Error>> {
+        let worlds = self.worlds.worlds();
+        let chunks = packet_chunks(&worlds, MAX_SERVERS)?;
+
+        for (i, chunk) in chunks.iter().enumerate() {
+            let offset = (i * MAX_SERVERS) as u16;
+            let mut server_list = IPCServerList::new(seq, offset, i == chunks.len() - 1);
+
+            for world in chunk.iter() {
+                println!(
+                    "world {} ({}, {}): {:?} with {} online",
+                    world.id, world.name, world.data_centre, world.status, world.population
+                );
+
+                let server = IPCServer {
+                    id: world.id,
+                    flags: world.status.flags(),
+                    name: FixedStr::truncated(&world.name),
+                    ..Default::default()
+                };
+
+                server_list.add_server(server)?;
+            }
+
+            let mut writer = Cursor::new(Vec::new());
+            server_list
+                .write_to(&mut writer)
+                .expect("failed to write server list");
+
+            self.send_ipc_packet::<IPCServerList>(writer.get_ref())
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    async fn send_error(&mut self, seq: u64, error: LobbyError) -> Result<(), Box<dyn Error>> {
+        println!("sending error: {}", error);
+        let error = IPCLobbyError::new(seq, error);
+
+        let mut writer = Cursor::new(Vec::new());
+        error
+            .write_to(&mut writer)
+            .expect("failed to write lobby error");
+
+        self.send_ipc_packet::<IPCLobbyError>(writer.get_ref())
+            .await
+    }
+
+    // the client answers with the same id and timestamp, which gives us the round trip.
+    // anything it sends counts as being alive, the answers just make sure there's something
+    async fn send_keepalive(&mut self) -> Result<(), Box<dyn Error>> {
+        let idle = self.last_received.elapsed();
+        if idle >= Duration::from_secs(self.config.idle_timeout) {
+            return Err(format!("idle for {}s", idle.as_secs()).into());
+        }
+
+        self.keepalive_id = self.keepalive_id.wrapping_add(1);
+        let keepalive = KeepAlive {
+            id: self.keepalive_id,
+            timestamp: self.connected_at.elapsed().as_millis() as u32,
+        };
+
+        let mut data = Cursor::new(Vec::new());
+        keepalive
+            .write_to(&mut data)
+            .expect("failed to write keepalive");
+
+        let segment_header = PacketSegmentHeader::new(
+            SegmentType::KeepAlive as u16,
+            data.get_ref().len() as u32,
+            self.actor_id,
+            self.actor_id,
+        );
+        self.send_packet(segment_header, data.get_ref()).await
+    }
+
+    // the opcode table for the client's game version. before one's been negotiated, which only
+    // happens with clients that never start encryption, it's our preferred version's
+    fn opcodes(&self) -> Result<&OpcodeTable, Box<dyn Error>> {
+        let game_version = self
+            .game_version
+            .or_else(|| self.config.client_versions.first().map(|v| v.game_version))
+            .ok_or("no game versions configured")?;
+
+        self.config
+            .opcode_tables
+            .get(game_version)
+            .ok_or_else(|| format!("no opcodes for game version {}", game_version).into())
+    }
+
+    fn opcode<T: IpcPacket>(&self) -> Result<u16, Box<dyn Error>> {
+        self.opcodes()?
+            .opcode_of::<T>()
+            .ok_or_else(|| format!("no opcode for {} in this game version", T::NAME).into())
+    }
+
+    async fn send_ipc_packet<T: IpcPacket>(
         &mut self,
-        ipc_type: u16,
         mut data: &[u8],
     ) -> Result<(), Box<dyn Error>> {
-        let ipc_header = IPCHeader::new(0, ipc_type);
+        let ipc_header = IPCHeader::new(0, self.opcode::<T>()?);
         let size = (size_of::<IPCHeader>() as u32) + (data.len() as u32);
 
-        let segment_header = PacketSegmentHeader::new(3, size, 0xe001c898, 0xe001c898);
+        let segment_header = PacketSegmentHeader::new(3, size, self.actor_id, self.actor_id);
         let mut buf: Cursor<Vec<u8>> = Cursor::new(vec![0; size as usize]);
 
         ipc_header
@@ -213,74 +958,30 @@ impl Client {
         &mut self,
         packets: &[(PacketSegmentHeader, &[u8])],
     ) -> Result<(), Box<dyn Error>> {
-        let mut send_cursor = Cursor::new(Vec::new());
-        let mut size = size_of::<PacketHeader>();
-        for (_, data) in packets.iter() {
-            size += size_of::<PacketSegmentHeader>() + data.len();
-        }
-
-        let packet_header = PacketHeader {
-            unknown_0: 0,
-            unknown_8: 0,
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64;
 
-            timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as u64,
-            size: size as u32,
-            connection_type: 0,
-            count: packets.len() as u16,
-
-            unknown_20: 1,
-            is_compressed: 0,
-            unknown_24: 0,
-            uncompressed_size: 0,
-        };
-
-        packet_header
-            .write_to(&mut send_cursor)
-            .expect("could not write packet header");
-
-        for (segment_header, mut data) in packets.iter() {
-            segment_header
-                .write_to(&mut send_cursor)
-                .expect("could not write packet segment header");
-
-            let mut packet_data_cursor = Cursor::new(Vec::new());
-            packet_data_cursor
-                .write_buf(&mut data)
-                .await
-                .expect("could not write packet data");
-
-            let segment_type = SegmentType::try_from(segment_header.segment_type).ok();
-            let unencrypted_data = packet_data_cursor.get_ref();
+        let capture = &mut self.capture;
+        let encryption_key = &self.encryption_key;
+        let sending = write_frame(timestamp, packets, |segment_header, data| {
+            if let Some(capture) = capture {
+                capture.segment(DIRECTION_SERVER_TO_CLIENT, segment_header, data);
+            }
 
             // encrypt with brokefish
-            if segment_type == Some(SegmentType::Ipc) && self.encryption_key.is_some() {
-                let enc_key = self.encryption_key.as_ref().unwrap();
-
-                let bf = Brokefish::new(enc_key);
-                let data_to_encrypt = &unencrypted_data[0..unencrypted_data.len() - 0x10];
-                let mut enc_data: Vec<u8> = vec![0; unencrypted_data.len()];
-
-                let enc_result = &bf.encrypt(data_to_encrypt);
-                enc_data[0..enc_result.len()].copy_from_slice(enc_result);
-
-                send_cursor
-                    .write_buf(&mut &enc_data[..])
-                    .await
-                    .expect("could not write encrypted packet data");
-            } else {
-                send_cursor
-                    .write_buf(&mut &unencrypted_data[..])
-                    .await
-                    .expect("could not write packet data");
+            let segment_type = SegmentType::try_from(segment_header.segment_type).ok();
+            match (segment_type, encryption_key) {
+                (Some(SegmentType::Ipc), Some(enc_key)) => {
+                    encrypt_ipc(enc_key, Direction::ServerToClient, data)
+                }
+                _ => data.to_vec(),
             }
-        }
+        });
 
-        let sending = send_cursor.get_ref();
         println!("sending packet: {:02X?}", sending);
-        self.stream
-            .write_all(sending)
-            .await
-            .expect("could not write packet");
+        if let Some(capture) = &mut self.capture {
+            capture.frame(DIRECTION_SERVER_TO_CLIENT, &sending);
+        }
+        self.stream.write_all(&sending).await?;
 
         Ok(())
     }
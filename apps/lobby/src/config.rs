@@ -0,0 +1,207 @@
+use character::NameRules;
+use sapphire_protocol::{fixed_str::FixedStr, opcodes::OpcodeTables, packets::FrameLimits};
+use serde::Deserialize;
+use std::{env, error::Error, fs, io::ErrorKind, path::Path};
+
+use crate::ipc::{HOST_SIZE, LOBBY_IPCS};
+
+// the opcodes we ship with, used when there's no opcodes file
+pub const DEFAULT_OPCODES: &str = include_str!("../opcodes.toml");
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub listen: String,
+    pub database: String,
+    pub client_versions: Vec<ClientVersion>,
+    // the data centre this lobby serves, only its worlds are listed
+    pub data_centre: String,
+    pub worlds: Vec<WorldConfig>,
+    // udp address world servers send their heartbeats to
+    pub heartbeat_listen: Option<String>,
+    // seconds without a heartbeat before a world shows as under maintenance
+    pub heartbeat_timeout: u64,
+    // seconds between queue position updates sent to queued clients
+    pub queue_update_interval: u64,
+    // assumed seconds per place in the queue until we've seen enough logins to estimate it
+    pub queue_default_wait: u64,
+    // seconds between the keepalives we send each client
+    pub keepalive_interval: u64,
+    // seconds without hearing anything from a client before it's dropped
+    pub idle_timeout: u64,
+    // seconds between printing every connection's latency, 0 to never print
+    pub connection_stats_interval: u64,
+    // record every connection's traffic to a file in here, see capture.rs
+    pub capture_dir: Option<String>,
+    // character names containing any of these, in any case, are refused
+    pub banned_name_words: Vec<String>,
+    // whole names nobody can create, in any case
+    pub reserved_names: Vec<String>,
+    // anything a client sends over these gets its connection closed
+    pub max_frame_size: usize,
+    pub max_segments_per_frame: u16,
+    pub max_ipc_size: usize,
+    // the file opcode tables are read from, see opcodes.toml. relative to the config file, and
+    // when it isn't set an opcodes.toml next to it is used if there is one
+    pub opcodes: Option<String>,
+    #[serde(skip)]
+    pub opcode_tables: OpcodeTables,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ClientVersion {
+    // the build number the client mixes into its encryption key (6100 for 6.1)
+    pub game_version: u16,
+    // version strings the client may report in ClientVersionInfo, empty accepts any
+    #[serde(default)]
+    pub versions: Vec<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct WorldConfig {
+    pub id: u16,
+    pub name: String,
+    pub data_centre: String,
+    // where the world server accepts the clients we hand off
+    pub host: String,
+    pub port: u16,
+    // population at which the world shows as congested
+    pub congested_at: u32,
+    // population at which new logins have to queue
+    pub capacity: u32,
+    #[serde(default)]
+    pub closed_for_creation: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let limits = FrameLimits::default();
+        Config {
+            listen: "0.0.0.0:42069".to_string(),
+            database: "lobby.db".to_string(),
+            client_versions: vec![ClientVersion {
+                game_version: 6100,
+                versions: Vec::new(),
+            }],
+            data_centre: "Sapphire".to_string(),
+            worlds: Vec::new(),
+            heartbeat_listen: None,
+            heartbeat_timeout: 30,
+            queue_update_interval: 10,
+            queue_default_wait: 30,
+            keepalive_interval: 10,
+            idle_timeout: 60,
+            connection_stats_interval: 0,
+            capture_dir: None,
+            banned_name_words: Vec::new(),
+            reserved_names: Vec::new(),
+            max_frame_size: limits.max_frame_size,
+            max_segments_per_frame: limits.max_segments,
+            max_ipc_size: limits.max_ipc_size,
+            opcodes: None,
+            opcode_tables: OpcodeTables::parse(DEFAULT_OPCODES)
+                .expect("the default opcodes don't parse"),
+        }
+    }
+}
+
+impl Config {
+    // a missing file isn't an error, you just get the defaults. the same goes for an
+    // opcodes.toml that was never asked for, but not for one that was
+    pub fn load(path: &str) -> Result<Config, Box<dyn Error>> {
+        let mut config: Config = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents)?,
+            Err(e) if e.kind() == ErrorKind::NotFound => Config::default(),
+            Err(e) => return Err(e.into()),
+        };
+        // where the database went before there was a config, still honoured over the file
+        if let Ok(database) = env::var("LOBBY_DATABASE") {
+            config.database = database;
+        }
+
+        let dir = Path::new(path).parent().unwrap_or_else(|| Path::new(""));
+        let opcodes_source = match &config.opcodes {
+            Some(opcodes) => {
+                let opcodes = dir.join(opcodes).to_string_lossy().into_owned();
+                config.opcode_tables = OpcodeTables::load(&opcodes)?;
+                opcodes
+            }
+            None => {
+                let opcodes = dir.join("opcodes.toml");
+                if opcodes.exists() {
+                    let opcodes = opcodes.to_string_lossy().into_owned();
+                    config.opcode_tables = OpcodeTables::load(&opcodes)?;
+                    opcodes
+                } else {
+                    println!("no {}, using the built in opcodes", opcodes.display());
+                    "the built in opcodes".to_string()
+                }
+            }
+        };
+
+        // a client we'd let in but couldn't understand is worse than not starting
+        for version in &config.client_versions {
+            let table = config
+                .opcode_tables
+                .get(version.game_version)
+                .ok_or_else(|| {
+                    format!(
+                        "no opcodes for game version {} in {}",
+                        version.game_version, opcodes_source
+                    )
+                })?;
+
+            for &(direction, name) in LOBBY_IPCS {
+                if table.opcode(direction, name).is_none() {
+                    return Err(format!(
+                        "no opcode for {} in game version {} of {}",
+                        name, version.game_version, opcodes_source
+                    )
+                    .into());
+                }
+            }
+        }
+
+        // every connection times its queue updates and keepalives with these, and a timer
+        // can't tick every 0s
+        if config.queue_update_interval == 0 {
+            return Err("queue_update_interval has to be at least 1 second".into());
+        }
+        if config.keepalive_interval == 0 {
+            return Err("keepalive_interval has to be at least 1 second".into());
+        }
+        // idleness is checked as each keepalive goes out, so anything shorter than the gap
+        // between them drops clients before they've had a chance to answer
+        if config.idle_timeout <= config.keepalive_interval {
+            return Err("idle_timeout has to be longer than keepalive_interval".into());
+        }
+
+        // cut short, a host would send clients somewhere else
+        for world in &config.worlds {
+            if let Err(e) = FixedStr::<HOST_SIZE>::new(&world.host) {
+                return Err(format!("world {}'s host is too long: {}", world.name, e).into());
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn is_version_allowed(&self, game_version: u16, version: &str) -> bool {
+        self.client_versions.iter().any(|allowed| {
+            allowed.game_version == game_version
+                && (allowed.versions.is_empty() || allowed.versions.iter().any(|v| v == version))
+        })
+    }
+
+    pub fn name_rules(&self) -> NameRules {
+        NameRules::new(&self.banned_name_words, &self.reserved_names)
+    }
+
+    pub fn frame_limits(&self) -> FrameLimits {
+        FrameLimits {
+            max_frame_size: self.max_frame_size,
+            max_segments: self.max_segments_per_frame,
+            max_ipc_size: self.max_ipc_size,
+        }
+    }
+}
@@ -0,0 +1,77 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+pub struct ConnectionStats {
+    pub connected_at: Instant,
+    pub account_id: Option<u32>,
+    // round trip of the last keepalive the client answered
+    pub latency: Option<Duration>,
+}
+
+// every open connection by its actor id, so there's something to look at when a client
+// says the lobby is slow
+#[derive(Clone, Default)]
+pub struct Connections {
+    stats: Arc<Mutex<HashMap<u32, ConnectionStats>>>,
+}
+
+impl Connections {
+    pub fn open(&self, actor_id: u32) {
+        self.stats
+            .lock()
+            .expect("connections lock poisoned")
+            .insert(
+                actor_id,
+                ConnectionStats {
+                    connected_at: Instant::now(),
+                    account_id: None,
+                    latency: None,
+                },
+            );
+    }
+
+    pub fn close(&self, actor_id: u32) {
+        self.stats
+            .lock()
+            .expect("connections lock poisoned")
+            .remove(&actor_id);
+    }
+
+    pub fn update(&self, actor_id: u32, update: impl FnOnce(&mut ConnectionStats)) {
+        if let Some(stats) = self
+            .stats
+            .lock()
+            .expect("connections lock poisoned")
+            .get_mut(&actor_id)
+        {
+            update(stats);
+        }
+    }
+
+    pub fn print(&self) {
+        let stats = self.stats.lock().expect("connections lock poisoned");
+        println!("{} open connections", stats.len());
+
+        for (actor_id, stats) in stats.iter() {
+            let account = match stats.account_id {
+                Some(id) => id.to_string(),
+                None => "none".to_string(),
+            };
+            let latency = match stats.latency {
+                Some(latency) => format!("{}ms", latency.as_millis()),
+                None => "unknown".to_string(),
+            };
+
+            println!(
+                "  {:#x}: account {}, latency {}, open for {}s",
+                actor_id,
+                account,
+                latency,
+                stats.connected_at.elapsed().as_secs()
+            );
+        }
+    }
+}
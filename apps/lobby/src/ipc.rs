@@ -1,95 +1,403 @@
-use binrw::BinWrite;
-use num_enum::TryFromPrimitive;
-use std::time::{SystemTime, UNIX_EPOCH};
-
-#[derive(BinWrite)]
-pub struct IPCHeader {
-    reserved: u16,
-    ipc_type: u16,
-    padding: u16,
-    server_id: u16,
-    timestamp: u32,
-    padding1: u32,
-}
-
-// todo: make this a trait or whatever rust shit can save me from this fresh hell
-impl IPCHeader {
-    pub fn new(server_id: u16, ipc_type: u16) -> IPCHeader {
-        IPCHeader {
-            reserved: 0,
-            ipc_type,
-            padding: 0,
-            server_id,
-            timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .expect("time went backwards")
-                .as_secs() as u32,
-            padding1: 0,
+use crate::lobby_error::LobbyError;
+use sapphire_protocol::{
+    fixed_str::FixedStr,
+    ipc::{Direction, IpcPacket},
+    ipc_struct,
+};
+use std::{error::Error, fmt};
+
+#[ipc_struct(
+    name = "Error",
+    direction = server_to_client,
+    size = 0x216
+)]
+pub struct IPCLobbyError {
+    pub seq: u64,
+    pub error_id: u32,
+    pub param: u32,
+    pub message_id: u16,
+    pub message: FixedStr<516>,
+}
+
+impl IPCLobbyError {
+    // the client shows its own text for message_id, message only fills in placeholders
+    pub fn new(seq: u64, error: LobbyError) -> IPCLobbyError {
+        IPCLobbyError {
+            seq,
+            error_id: error.error_id(),
+            message_id: error.message_id(),
+            ..Default::default()
         }
     }
 }
 
-#[derive(TryFromPrimitive)]
-#[repr(u16)]
-pub enum ClientLobbyIpcType {
-    ReqCharList = 0x0003,
-    ReqEnterWorld = 0x0004,
-    ClientVersionInfo = 0x0005,
+#[ipc_struct(
+    name = "ClientVersionInfo",
+    direction = client_to_server,
+    size = 0xd2
+)]
+pub struct IPCClientVersionInfo {
+    pub seq: u64,
+    pub unknown: [u8; 10],
+    pub session_id: FixedStr<0x40>,
+    pub version: FixedStr<0x80>,
+}
+
+#[ipc_struct(
+    name = "ReqCharList",
+    direction = client_to_server,
+    size = 0x8
+)]
+pub struct IPCReqCharList {
+    pub seq: u64,
+}
+
+#[ipc_struct(
+    name = "ReqCharDelete",
+    direction = client_to_server,
+    size = 0x3c
+)]
+pub struct IPCReqCharDelete {
+    pub seq: u64,
+    pub content_id: u64,
+    pub unknown: [u8; 12],
+    pub name: FixedStr<0x20>,
+}
 
-    ReqCharDelete = 0x000a,
-    ReqCharCreate = 0x000b,
+#[ipc_struct(
+    name = "ReqEnterWorld",
+    direction = client_to_server,
+    size = 0x10
+)]
+pub struct IPCReqEnterWorld {
+    pub seq: u64,
+    pub content_id: u64,
 }
 
-#[derive(BinWrite, Copy, Clone)]
+#[ipc_struct(size = 0x50)]
+#[derive(Clone)]
 pub struct IPCServiceAccount {
     pub id: u32,
     pub unknown: u32,
     pub index: u32,
-    pub name: [u8; 0x44],
+    pub name: FixedStr<0x44>,
 }
 
-impl Default for IPCServiceAccount {
-    fn default() -> Self {
-        IPCServiceAccount {
-            id: 0,
-            unknown: 0,
-            index: 0,
-            name: [0; 0x44],
-        }
+pub const MAX_SERVICE_ACCOUNTS: usize = 8;
+
+// returned when adding to a list packet that's already full
+#[derive(Debug)]
+pub struct ListFull {
+    pub capacity: usize,
+}
+
+impl fmt::Display for ListFull {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "list packet holds at most {} entries", self.capacity)
     }
 }
 
-#[derive(BinWrite)]
+impl Error for ListFull {}
+
+#[ipc_struct(
+    name = "ServiceAccountList",
+    direction = server_to_client,
+    size = 0x28d
+)]
 pub struct IPCServiceIDInfo {
     seq: u64,
-    padding: u8,
+    // lists longer than one packet are numbered like the character list:
+    // the packet index times four, with the low bit set on the last packet
+    counter: u8,
     service_accounts_len: u8,
     u1: u8,
     u2: u8,
-    padding1: u8,
-    service_accounts: [IPCServiceAccount; 8],
+    #[ipc(offset = 0x0d)]
+    service_accounts: [IPCServiceAccount; MAX_SERVICE_ACCOUNTS],
 }
 
-impl Default for IPCServiceIDInfo {
-    fn default() -> IPCServiceIDInfo {
+impl IPCServiceIDInfo {
+    pub fn new(seq: u64, packet_index: u8, is_last: bool) -> IPCServiceIDInfo {
         IPCServiceIDInfo {
-            seq: 1,
-            padding: 0,
-            service_accounts_len: 0,
+            seq,
+            counter: (packet_index << 2) | is_last as u8,
             u1: 3,
             u2: 0x99,
-            padding1: 0,
-            service_accounts: [IPCServiceAccount::default(); 8],
+            ..Default::default()
         }
     }
-}
 
-impl IPCServiceIDInfo {
-    pub fn add_service_account(&mut self, mut acc: IPCServiceAccount) {
-        let idx = self.service_accounts_len;
+    // the index is the account's position in the whole list, not just in this packet
+    pub fn add_service_account(&mut self, mut acc: IPCServiceAccount) -> Result<(), ListFull> {
+        let idx = self.service_accounts_len as usize;
+        if idx >= MAX_SERVICE_ACCOUNTS {
+            return Err(ListFull {
+                capacity: MAX_SERVICE_ACCOUNTS,
+            });
+        }
 
-        acc.index = idx as u32;
+        let packet_index = (self.counter >> 2) as usize;
+        acc.index = (packet_index * MAX_SERVICE_ACCOUNTS + idx) as u32;
         self.service_accounts_len += 1;
-        self.service_accounts[idx as usize] = acc;
+        self.service_accounts[idx] = acc;
+
+        Ok(())
+    }
+}
+
+pub const MAX_SERVERS: usize = 6;
+
+#[ipc_struct(size = 0x54)]
+#[derive(Clone)]
+pub struct IPCServer {
+    pub id: u16,
+    pub index: u16,
+    pub flags: u32,
+    #[ipc(offset = 0x0c)]
+    pub icon: u32,
+    #[ipc(offset = 0x14)]
+    pub name: FixedStr<0x40>,
+}
+
+#[ipc_struct(
+    name = "ServerList",
+    direction = server_to_client,
+    size = 0x210
+)]
+pub struct IPCServerList {
+    seq: u64,
+    // non-zero on the last packet of the list
+    last: u16,
+    // how many servers came before this packet
+    offset: u16,
+    servers_len: u32,
+    #[ipc(offset = 0x18)]
+    servers: [IPCServer; MAX_SERVERS],
+}
+
+impl IPCServerList {
+    pub fn new(seq: u64, offset: u16, is_last: bool) -> IPCServerList {
+        IPCServerList {
+            seq,
+            last: is_last as u16,
+            offset,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_server(&mut self, mut server: IPCServer) -> Result<(), ListFull> {
+        let idx = self.servers_len as usize;
+        if idx >= MAX_SERVERS {
+            return Err(ListFull {
+                capacity: MAX_SERVERS,
+            });
+        }
+
+        server.index = self.offset + idx as u16;
+        self.servers_len += 1;
+        self.servers[idx] = server;
+
+        Ok(())
+    }
+}
+
+pub const MAX_CHARACTERS: usize = 2;
+
+#[ipc_struct(size = 0x49f)]
+#[derive(Clone)]
+pub struct IPCCharacter {
+    pub id: u32,
+    #[ipc(offset = 0x08)]
+    pub content_id: u64,
+    pub index: u32,
+    #[ipc(offset = 0x18)]
+    pub world_id: u16,
+    pub current_world_id: u16,
+    pub unknown: [u8; 9],
+    pub name: FixedStr<0x20>,
+    pub world_name: FixedStr<0x20>,
+    pub current_world_name: FixedStr<0x20>,
+    pub detail_json: FixedStr<1050>,
+}
+
+#[ipc_struct(
+    name = "CharList",
+    direction = server_to_client,
+    size = 0x99a
+)]
+pub struct IPCCharList {
+    seq: u64,
+    // the packet index times four, with the low bit set on the last packet
+    counter: u8,
+    characters_len: u8,
+    #[ipc(offset = 0x0c)]
+    unknown: [u32; 12],
+    // the account details below only matter on the last packet
+    pub veteran_rank: u32,
+    unknown1: u32,
+    pub days_subscribed: u32,
+    pub remaining_days: u32,
+    pub days_to_next_rank: u32,
+    pub max_characters_on_world: u16,
+    unknown2: u16,
+    pub entitled_expansion: u32,
+    #[ipc(offset = 0x5c)]
+    characters: [IPCCharacter; MAX_CHARACTERS],
+}
+
+impl IPCCharList {
+    pub fn new(seq: u64, packet_index: u8, is_last: bool) -> IPCCharList {
+        IPCCharList {
+            seq,
+            counter: (packet_index << 2) | is_last as u8,
+            ..Default::default()
+        }
+    }
+
+    pub fn add_character(&mut self, mut character: IPCCharacter) -> Result<(), ListFull> {
+        let idx = self.characters_len as usize;
+        if idx >= MAX_CHARACTERS {
+            return Err(ListFull {
+                capacity: MAX_CHARACTERS,
+            });
+        }
+
+        let packet_index = (self.counter >> 2) as usize;
+        character.index = (packet_index * MAX_CHARACTERS + idx) as u32;
+        self.characters_len += 1;
+        self.characters[idx] = character;
+
+        Ok(())
+    }
+}
+
+pub const MAX_RETAINERS: usize = 9;
+
+#[ipc_struct(size = 0x38)]
+#[derive(Clone)]
+pub struct IPCRetainer {
+    pub id: u64,
+    pub owner_content_id: u64,
+    pub class_job: u8,
+    pub level: u8,
+    #[ipc(offset = 0x18)]
+    pub name: FixedStr<0x20>,
+}
+
+#[ipc_struct(
+    name = "RetainerList",
+    direction = server_to_client,
+    size = 0x208
+)]
+pub struct IPCRetainerList {
+    seq: u64,
+    // the packet index times four, with the low bit set on the last packet
+    counter: u8,
+    retainers_len: u8,
+    #[ipc(offset = 0x10)]
+    retainers: [IPCRetainer; MAX_RETAINERS],
+}
+
+impl IPCRetainerList {
+    pub fn new(seq: u64, packet_index: u8, is_last: bool) -> IPCRetainerList {
+        IPCRetainerList {
+            seq,
+            counter: (packet_index << 2) | is_last as u8,
+            ..Default::default()
+        }
     }
+
+    pub fn add_retainer(&mut self, retainer: IPCRetainer) -> Result<(), ListFull> {
+        let idx = self.retainers_len as usize;
+        if idx >= MAX_RETAINERS {
+            return Err(ListFull {
+                capacity: MAX_RETAINERS,
+            });
+        }
+
+        self.retainers_len += 1;
+        self.retainers[idx] = retainer;
+
+        Ok(())
+    }
+}
+
+pub const CHAR_CREATE_TYPE_DELETE: u8 = 4;
+
+// answers every step of creating a character and also confirms deletion
+#[ipc_struct(
+    name = "CharCreate",
+    direction = server_to_client,
+    size = 0x8c
+)]
+pub struct IPCCharCreate {
+    pub seq: u64,
+    pub unknown: u8,
+    pub unknown1: u8,
+    pub create_type: u8,
+    #[ipc(offset = 0x0c)]
+    pub unknown2: [u32; 3],
+    pub content_id: u64,
+    pub unknown3: [u8; 12],
+    pub name: FixedStr<0x20>,
+    pub world_name: FixedStr<0x20>,
+    pub current_world_name: FixedStr<0x20>,
+}
+
+impl IPCCharCreate {
+    pub fn new(seq: u64, create_type: u8, content_id: u64) -> IPCCharCreate {
+        IPCCharCreate {
+            seq,
+            unknown1: 1,
+            create_type,
+            content_id,
+            ..Default::default()
+        }
+    }
+}
+
+// how much room EnterWorld has for a world's host, null included
+pub const HOST_SIZE: usize = 48;
+
+// hands the client off to a world server
+#[ipc_struct(
+    name = "EnterWorld",
+    direction = server_to_client,
+    size = 0xa0
+)]
+pub struct IPCEnterWorld {
+    pub seq: u64,
+    pub character_id: u32,
+    #[ipc(offset = 0x10)]
+    pub content_id: u64,
+    #[ipc(offset = 0x1c)]
+    pub session_id: FixedStr<66>,
+    pub port: u16,
+    pub host: FixedStr<HOST_SIZE>,
 }
+
+impl IPCEnterWorld {
+    pub fn new(seq: u64, character_id: u32, content_id: u64) -> IPCEnterWorld {
+        IPCEnterWorld {
+            seq,
+            character_id,
+            content_id,
+            ..Default::default()
+        }
+    }
+}
+
+// every IPC the lobby reads or sends, so each client version's opcode table has to have them
+pub const LOBBY_IPCS: &[(Direction, &str)] = &[
+    (IPCClientVersionInfo::DIRECTION, IPCClientVersionInfo::NAME),
+    (IPCReqCharList::DIRECTION, IPCReqCharList::NAME),
+    (IPCReqCharDelete::DIRECTION, IPCReqCharDelete::NAME),
+    (IPCReqEnterWorld::DIRECTION, IPCReqEnterWorld::NAME),
+    (IPCLobbyError::DIRECTION, IPCLobbyError::NAME),
+    (IPCServiceIDInfo::DIRECTION, IPCServiceIDInfo::NAME),
+    (IPCServerList::DIRECTION, IPCServerList::NAME),
+    (IPCCharList::DIRECTION, IPCCharList::NAME),
+    (IPCRetainerList::DIRECTION, IPCRetainerList::NAME),
+    (IPCCharCreate::DIRECTION, IPCCharCreate::NAME),
+    (IPCEnterWorld::DIRECTION, IPCEnterWorld::NAME),
+];
@@ -0,0 +1,7 @@
+pub mod capture;
+pub mod client;
+pub mod config;
+pub mod connections;
+pub mod ipc;
+pub mod lobby_error;
+pub mod worlds;
@@ -0,0 +1,87 @@
+use character::NameError;
+use std::{error::Error, fmt};
+
+// the reasons we turn a client away. each one pairs the error code the client shows
+// ("error 5006") with the id of the message it looks up in its own text data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LobbyError {
+    ClientOutOfDate,
+    InvalidSession,
+    NoServiceAccounts,
+    Maintenance,
+    WorldFull,
+    // sent over and over while queued, with the position as its param
+    InQueue,
+    WorldUnavailable,
+    WorldClosedForCreation,
+    CharacterNotFound,
+    CharacterHasRetainers,
+    // a name that breaks the rules, each with its own message
+    Name(NameError),
+    Internal,
+}
+
+impl LobbyError {
+    pub fn error_id(self) -> u32 {
+        match self {
+            LobbyError::ClientOutOfDate => 1012,
+            LobbyError::InvalidSession => 5006,
+            LobbyError::NoServiceAccounts => 5006,
+            LobbyError::Maintenance => 2002,
+            LobbyError::WorldFull => 3001,
+            LobbyError::InQueue => 3001,
+            LobbyError::WorldUnavailable => 3001,
+            LobbyError::WorldClosedForCreation => 3006,
+            LobbyError::CharacterNotFound => 3001,
+            LobbyError::CharacterHasRetainers => 3006,
+            LobbyError::Name(_) => 3006,
+            LobbyError::Internal => 2002,
+        }
+    }
+
+    pub fn message_id(self) -> u16 {
+        match self {
+            LobbyError::ClientOutOfDate => 13101,
+            LobbyError::InvalidSession => 13001,
+            LobbyError::NoServiceAccounts => 13206,
+            LobbyError::Maintenance => 13005,
+            LobbyError::WorldFull => 13202,
+            LobbyError::InQueue => 13204,
+            LobbyError::WorldUnavailable => 13203,
+            LobbyError::WorldClosedForCreation => 13008,
+            LobbyError::CharacterNotFound => 13007,
+            LobbyError::CharacterHasRetainers => 13010,
+            LobbyError::Name(e) => match e {
+                NameError::Taken => 13004,
+                NameError::Parts => 13019,
+                NameError::Length => 13020,
+                NameError::Characters => 13021,
+                NameError::Capitalisation => 13022,
+                NameError::Punctuation => 13023,
+                NameError::BannedWord => 13024,
+                NameError::Reserved => 13025,
+            },
+            LobbyError::Internal => 13002,
+        }
+    }
+}
+
+impl fmt::Display for LobbyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:?} (error {}, message {})",
+            self,
+            self.error_id(),
+            self.message_id()
+        )
+    }
+}
+
+impl Error for LobbyError {}
+
+impl From<NameError> for LobbyError {
+    fn from(e: NameError) -> Self {
+        LobbyError::Name(e)
+    }
+}
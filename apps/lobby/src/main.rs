@@ -1,29 +1,197 @@
-mod client;
-mod ipc;
-mod packets;
+use character::{detail_customize, NameError};
+use lobby::{
+    client::Client,
+    config::Config,
+    connections::Connections,
+    lobby_error::LobbyError,
+    worlds::{self, WorldRegistry},
+};
+use std::{env, error::Error, sync::Arc, time::Duration};
+use storage::{SqliteStorage, Storage};
+use tokio::{
+    net::{TcpListener, TcpStream, UdpSocket},
+    time,
+};
 
-use client::Client;
-use std::error::Error;
-use tokio::net::{TcpListener, TcpStream};
+const USAGE: &str = "usage:
+    lobby
+    lobby account add <username>
+    lobby account session <account id> <session id>
+    lobby service-account add <account id> <name>
+    lobby service-account enable <service account id>
+    lobby service-account disable <service account id>
+    lobby service-account list <account id>
+    lobby character add <account id> <world id> \"<forename> <surname>\"
+    lobby character list <account id>
+    lobby character detail <character id> <json file>
+    lobby retainer add <character id> <name>
+    lobby retainer remove <retainer id>
+    lobby queue list [world id]
+    lobby queue clear [world id]";
 
-fn handle_stream(stream: TcpStream) {
+fn handle_stream(
+    stream: TcpStream,
+    db: Arc<dyn Storage>,
+    config: Arc<Config>,
+    worlds: WorldRegistry,
+    connections: Connections,
+) {
     tokio::spawn(async move {
-        let mut client = Client {
-            stream,
-            encryption_key: None,
-        };
+        let mut client = Client::new(stream, db, config, worlds, connections);
 
         client.handle().await;
     });
 }
 
+// these work on the same database as a running server, which reads service accounts on
+// every login, so there's no need to restart it
+fn run_admin_command(
+    db: &dyn Storage,
+    config: &Config,
+    args: &[String],
+) -> Result<(), Box<dyn Error>> {
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+
+    match args[..] {
+        ["account", "add", username] => {
+            let id = db.add_account(username)?;
+            println!("added account {}", id);
+        }
+        ["account", "session", account_id, session_id] => {
+            db.add_session(account_id.parse()?, session_id)?;
+            println!("added session for account {}", account_id);
+        }
+        ["service-account", "add", account_id, name] => {
+            let id = db.add_service_account(account_id.parse()?, name)?;
+            println!("added service account {}", id);
+        }
+        ["service-account", action @ ("enable" | "disable"), id] => {
+            if !db.set_service_account_enabled(id.parse()?, action == "enable")? {
+                return Err(format!("no service account with id {}", id).into());
+            }
+            println!("{}d service account {}", action, id);
+        }
+        ["service-account", "list", account_id] => {
+            // the index is where the client lists it, and disabled accounts aren't sent at all
+            let mut index = 0;
+            for account in db.service_accounts(account_id.parse()?)? {
+                if account.enabled {
+                    println!("{}: {} {}", index, account.id, account.name);
+                    index += 1;
+                } else {
+                    println!("-: {} {} (disabled)", account.id, account.name);
+                }
+            }
+        }
+        ["character", "add", account_id, world_id, name] => {
+            let world_id = world_id.parse()?;
+            // refused the same way a client creating it would be, with what it'd be shown
+            let rejection = match config.name_rules().validate(name) {
+                Err(e) => Some(e),
+                Ok(()) if db.character_name_taken(world_id, name)? => Some(NameError::Taken),
+                Ok(()) => None,
+            };
+            if let Some(e) = rejection {
+                return Err(format!("{}: {}", e, LobbyError::from(e)).into());
+            }
+
+            let character = db.add_character(account_id.parse()?, world_id, name)?;
+            println!(
+                "added character {} (content id {:#x}, character id {:#x})",
+                character.id, character.content_id, character.character_id
+            );
+        }
+        ["character", "list", account_id] => {
+            for character in db.characters(account_id.parse()?)? {
+                println!(
+                    "{} (content id {:#x}, character id {:#x}): {} on world {}",
+                    character.id,
+                    character.content_id,
+                    character.character_id,
+                    character.name,
+                    character.world_id
+                );
+            }
+        }
+        ["character", "detail", id, path] => {
+            let detail_json = std::fs::read_to_string(path)?;
+            detail_customize(&detail_json).map_err(|e| format!("{}: {}", path, e))?;
+            if !db.set_character_detail_json(id.parse()?, &detail_json)? {
+                return Err(format!("no character with id {}", id).into());
+            }
+            println!("set character {}'s description", id);
+        }
+        ["retainer", "add", character_id, name] => {
+            let id = db.add_retainer(character_id.parse()?, name)?;
+            println!("added retainer {}", id);
+        }
+        ["retainer", "remove", id] => {
+            if !db.remove_retainer(id.parse()?)? {
+                return Err(format!("no retainer with id {}", id).into());
+            }
+            println!("removed retainer {}", id);
+        }
+        ["queue", "list", ref world_id @ ..] if world_id.len() <= 1 => {
+            let world_id = world_id.first().map(|id| id.parse()).transpose()?;
+            for entry in db.queue(world_id)? {
+                println!(
+                    "world {}: ticket {} for content id {:#x}, joined at {}",
+                    entry.world_id, entry.ticket, entry.content_id, entry.joined_at
+                );
+            }
+        }
+        ["queue", "clear", ref world_id @ ..] if world_id.len() <= 1 => {
+            let world_id = world_id.first().map(|id| id.parse()).transpose()?;
+            let cleared = db.clear_queue(world_id)?;
+            println!("cleared {} queued logins", cleared);
+        }
+        _ => return Err(USAGE.into()),
+    }
+
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     color_eyre::install()?;
 
-    let listener = TcpListener::bind("0.0.0.0:42069").await?;
+    let config_path = env::var("LOBBY_CONFIG").unwrap_or_else(|_| "lobby.toml".to_string());
+    let config = Arc::new(Config::load(&config_path)?);
+    let db: Arc<dyn Storage> = Arc::new(SqliteStorage::open(&config.database)?);
+
+    let args: Vec<String> = env::args().skip(1).collect();
+    if !args.is_empty() {
+        return run_admin_command(db.as_ref(), &config, &args);
+    }
+
+    let worlds = WorldRegistry::new(config.clone());
+    if let Some(address) = &config.heartbeat_listen {
+        let socket = UdpSocket::bind(address).await?;
+        tokio::spawn(worlds::listen_for_heartbeats(worlds.clone(), socket));
+    }
+
+    let connections = Connections::default();
+    if config.connection_stats_interval > 0 {
+        let connections = connections.clone();
+        let period = Duration::from_secs(config.connection_stats_interval);
+        tokio::spawn(async move {
+            let mut timer = time::interval_at(time::Instant::now() + period, period);
+            loop {
+                timer.tick().await;
+                connections.print();
+            }
+        });
+    }
+
+    let listener = TcpListener::bind(&config.listen).await?;
     loop {
         let (socket, _) = listener.accept().await?;
-        handle_stream(socket);
+        handle_stream(
+            socket,
+            db.clone(),
+            config.clone(),
+            worlds.clone(),
+            connections.clone(),
+        );
     }
 }
@@ -0,0 +1,187 @@
+use binrw::BinRead;
+use std::{
+    collections::HashMap,
+    io::Cursor,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::net::UdpSocket;
+
+use crate::config::Config;
+
+pub const HEARTBEAT_MAINTENANCE: u16 = 1 << 0;
+pub const HEARTBEAT_CLOSED_FOR_CREATION: u16 = 1 << 1;
+
+// what a world server sends to heartbeat_listen every few seconds, one per datagram
+#[derive(BinRead, Debug)]
+pub struct WorldHeartbeat {
+    pub world_id: u16,
+    pub flags: u16,
+    pub population: u32,
+}
+
+// a world can be congested and closed for creation at once, so these are separate
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct WorldStatus {
+    // no recent heartbeat or the world says it's down, which hides the rest
+    pub maintenance: bool,
+    pub congested: bool,
+    pub closed_for_creation: bool,
+}
+
+impl WorldStatus {
+    // the server list flags the character select screen picks the world's icons from
+    pub fn flags(self) -> u32 {
+        if self.maintenance {
+            return 0x00;
+        }
+
+        let mut flags = 0x01;
+        if self.congested {
+            flags |= 0x02;
+        }
+        if self.closed_for_creation {
+            flags |= 0x04;
+        }
+        flags
+    }
+}
+
+pub struct WorldInfo {
+    pub id: u16,
+    pub name: String,
+    pub data_centre: String,
+    pub status: WorldStatus,
+    pub population: u32,
+}
+
+struct WorldState {
+    last_heartbeat: Instant,
+    flags: u16,
+    population: u32,
+    // logins handed off since the last heartbeat, which its population won't include yet
+    handoffs: u32,
+}
+
+// the configured worlds plus whatever their servers last told us about themselves
+#[derive(Clone)]
+pub struct WorldRegistry {
+    config: Arc<Config>,
+    states: Arc<Mutex<HashMap<u16, WorldState>>>,
+}
+
+impl WorldRegistry {
+    pub fn new(config: Arc<Config>) -> WorldRegistry {
+        WorldRegistry {
+            config,
+            states: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub fn heartbeat(&self, heartbeat: &WorldHeartbeat) {
+        if !self
+            .config
+            .worlds
+            .iter()
+            .any(|w| w.id == heartbeat.world_id)
+        {
+            println!("heartbeat from unknown world {}", heartbeat.world_id);
+            return;
+        }
+
+        self.states
+            .lock()
+            .expect("world state lock poisoned")
+            .insert(
+                heartbeat.world_id,
+                WorldState {
+                    last_heartbeat: Instant::now(),
+                    flags: heartbeat.flags,
+                    population: heartbeat.population,
+                    handoffs: 0,
+                },
+            );
+    }
+
+    // the worlds in this lobby's data centre, in the order they're configured
+    pub fn worlds(&self) -> Vec<WorldInfo> {
+        let states = self.states.lock().expect("world state lock poisoned");
+        let timeout = Duration::from_secs(self.config.heartbeat_timeout);
+
+        self.config
+            .worlds
+            .iter()
+            .filter(|world| world.data_centre == self.config.data_centre)
+            .map(|world| {
+                let state = states
+                    .get(&world.id)
+                    .filter(|state| state.last_heartbeat.elapsed() < timeout);
+
+                let status = match state {
+                    None => WorldStatus {
+                        maintenance: true,
+                        ..Default::default()
+                    },
+                    Some(state) => WorldStatus {
+                        maintenance: state.flags & HEARTBEAT_MAINTENANCE != 0,
+                        congested: state.population >= world.congested_at,
+                        closed_for_creation: world.closed_for_creation
+                            || state.flags & HEARTBEAT_CLOSED_FOR_CREATION != 0,
+                    },
+                };
+
+                WorldInfo {
+                    id: world.id,
+                    name: world.name.clone(),
+                    data_centre: world.data_centre.clone(),
+                    status,
+                    population: state.map(|state| state.population).unwrap_or(0),
+                }
+            })
+            .collect()
+    }
+
+    pub fn world(&self, id: u16) -> Option<WorldInfo> {
+        self.worlds().into_iter().find(|world| world.id == id)
+    }
+
+    pub fn has_free_slot(&self, world_id: u16) -> bool {
+        let capacity = match self.config.worlds.iter().find(|w| w.id == world_id) {
+            Some(world) => world.capacity,
+            None => return false,
+        };
+
+        let states = self.states.lock().expect("world state lock poisoned");
+        match states.get(&world_id) {
+            Some(state) => state.population + state.handoffs < capacity,
+            None => false,
+        }
+    }
+
+    pub fn record_handoff(&self, world_id: u16) {
+        let mut states = self.states.lock().expect("world state lock poisoned");
+        if let Some(state) = states.get_mut(&world_id) {
+            state.handoffs += 1;
+        }
+    }
+}
+
+// the socket's bound by the caller, so a bad address stops the lobby starting
+pub async fn listen_for_heartbeats(registry: WorldRegistry, socket: UdpSocket) {
+    let mut buf = [0; 64];
+
+    loop {
+        let (n, from) = match socket.recv_from(&mut buf).await {
+            Ok(received) => received,
+            Err(e) => {
+                println!("failed to receive heartbeat: {}", e);
+                continue;
+            }
+        };
+
+        match WorldHeartbeat::read(&mut Cursor::new(&buf[0..n])) {
+            Ok(heartbeat) => registry.heartbeat(&heartbeat),
+            Err(e) => println!("bad heartbeat from {}: {}", from, e),
+        }
+    }
+}
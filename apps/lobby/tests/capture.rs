@@ -0,0 +1,38 @@
+use lobby::capture::{read_capture, CAPTURE_MAGIC, CAPTURE_VERSION, MAX_RECORD_LEN};
+use std::fs;
+
+// a capture holding one record that claims len bytes and has data after it
+fn capture_with_record(name: &str, len: u32, data: &[u8]) -> std::path::PathBuf {
+    let mut file = Vec::new();
+    file.extend_from_slice(&CAPTURE_MAGIC);
+    file.extend_from_slice(&CAPTURE_VERSION.to_le_bytes());
+    file.extend_from_slice(&[0; 8 + 4 + 1 + 1 + 2 + 2 + 4 + 4]);
+    file.extend_from_slice(&len.to_le_bytes());
+    file.extend_from_slice(data);
+
+    let path = std::env::temp_dir().join(format!("capture-{}-{}.cap", name, std::process::id()));
+    fs::write(&path, file).unwrap();
+    path
+}
+
+#[test]
+fn records_read_back() {
+    let path = capture_with_record("ok", 3, b"abc");
+    let records = read_capture(&path).unwrap();
+    assert_eq!(records.len(), 1);
+    assert_eq!(records[0].data, b"abc");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn huge_records_are_refused_before_reading() {
+    let path = capture_with_record("huge", u32::MAX, b"abc");
+    assert!(read_capture(&path).is_err());
+    fs::remove_file(&path).unwrap();
+
+    // refused even when the file really does have that much after it
+    let len = MAX_RECORD_LEN + 1;
+    let path = capture_with_record("over", len, &vec![0; len as usize]);
+    assert!(read_capture(&path).is_err());
+    fs::remove_file(&path).unwrap();
+}
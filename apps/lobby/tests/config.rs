@@ -0,0 +1,147 @@
+use lobby::config::{Config, DEFAULT_OPCODES};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+// a directory of its own for each test, holding lobby.toml and whatever else it needs
+fn config_dir(name: &str, lobby_toml: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("lobby-config-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("lobby.toml"), lobby_toml).unwrap();
+    dir
+}
+
+fn load(dir: &Path) -> Result<Config, String> {
+    Config::load(&dir.join("lobby.toml").to_string_lossy()).map_err(|e| e.to_string())
+}
+
+#[test]
+fn missing_config_gives_the_defaults() {
+    let config = Config::load("/nonexistent/lobby.toml").unwrap();
+    assert_eq!(config.listen, Config::default().listen);
+    assert!(config.opcode_tables.get(6100).is_some());
+}
+
+#[test]
+fn opcodes_are_found_next_to_the_config() {
+    let dir = config_dir("next-to", "");
+    fs::write(
+        dir.join("opcodes.toml"),
+        DEFAULT_OPCODES.replace("CharList = 0x000d", "CharList = 0x0123"),
+    )
+    .unwrap();
+
+    let config = load(&dir).unwrap();
+    let table = config.opcode_tables.get(6100).unwrap();
+    assert_eq!(table.opcode_of::<lobby::ipc::IPCCharList>(), Some(0x123));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn opcodes_that_were_asked_for_have_to_exist() {
+    let dir = config_dir("missing", "opcodes = \"tables/6.1.toml\"");
+    let error = load(&dir).err().unwrap();
+    assert!(error.contains("6.1.toml"), "{}", error);
+
+    // and are relative to the config, not wherever the lobby was started
+    fs::create_dir(dir.join("tables")).unwrap();
+    fs::write(dir.join("tables").join("6.1.toml"), DEFAULT_OPCODES).unwrap();
+    assert!(load(&dir).is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn every_client_version_needs_every_opcode() {
+    let dir = config_dir("incomplete", "");
+    fs::write(
+        dir.join("opcodes.toml"),
+        DEFAULT_OPCODES.replace("RetainerList = 0x0017\n", ""),
+    )
+    .unwrap();
+    let error = load(&dir).err().unwrap();
+    assert!(error.contains("RetainerList"), "{}", error);
+
+    fs::write(dir.join("opcodes.toml"), DEFAULT_OPCODES).unwrap();
+    fs::write(
+        dir.join("lobby.toml"),
+        "[[client_versions]]\ngame_version = 6200",
+    )
+    .unwrap();
+    let error = load(&dir).err().unwrap();
+    assert!(error.contains("6200"), "{}", error);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn world_hosts_have_to_fit_enter_world() {
+    let host = "a".repeat(47);
+    let world = |host: &str| {
+        format!(
+            "[[worlds]]
+            id = 1
+            name = \"Ultros\"
+            data_centre = \"Sapphire\"
+            host = \"{}\"
+            port = 54992
+            congested_at = 500
+            capacity = 600",
+            host
+        )
+    };
+
+    let dir = config_dir("host", &world(&host));
+    assert!(load(&dir).is_ok());
+
+    fs::write(dir.join("lobby.toml"), world(&format!("{}a", host))).unwrap();
+    let error = load(&dir).err().unwrap();
+    assert!(error.contains("Ultros"), "{}", error);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn queue_updates_need_an_interval() {
+    let dir = config_dir("queue-interval", "queue_update_interval = 0");
+    let error = load(&dir).err().unwrap();
+    assert!(error.contains("queue_update_interval"), "{}", error);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn keepalives_need_an_interval() {
+    let dir = config_dir("keepalive-interval", "keepalive_interval = 0");
+    let error = load(&dir).err().unwrap();
+    assert!(error.contains("keepalive_interval"), "{}", error);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn idle_timeouts_have_to_outlast_a_keepalive() {
+    for idle_timeout in [0, 5, 10] {
+        let dir = config_dir(
+            "idle-timeout",
+            &format!("keepalive_interval = 10\nidle_timeout = {}", idle_timeout),
+        );
+        let error = load(&dir).err().unwrap();
+        assert!(error.contains("idle_timeout"), "{}", error);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    let dir = config_dir("idle-timeout", "keepalive_interval = 10\nidle_timeout = 11");
+    assert!(load(&dir).is_ok());
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn lobby_database_overrides_the_file() {
+    let dir = config_dir("database", "database = \"from-the-file.db\"");
+    assert_eq!(load(&dir).unwrap().database, "from-the-file.db");
+
+    // nothing else here looks at the database, so setting it for the whole process is safe
+    std::env::set_var("LOBBY_DATABASE", "from-the-environment.db");
+    let config = load(&dir);
+    std::env::remove_var("LOBBY_DATABASE");
+    assert_eq!(config.unwrap().database, "from-the-environment.db");
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,82 @@
+// inputs that used to crash the lobby, most of them found by the fuzz targets in fuzz/. the
+// parsers themselves are tested in sapphire-protocol
+use lobby::{client::Client, config::Config, connections::Connections, worlds::WorldRegistry};
+use sapphire_protocol::packets::SegmentType;
+use std::sync::Arc;
+use storage::SqliteStorage;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt};
+
+// a frame whose segment headers say whatever we like, regardless of the data that follows
+fn frame(count: u16, segments: &[(u16, u32, &[u8])]) -> Vec<u8> {
+    let data_len: usize = segments.iter().map(|(_, _, data)| 16 + data.len()).sum();
+
+    let mut frame = vec![0; 40];
+    frame[24..28].copy_from_slice(&((40 + data_len) as u32).to_le_bytes());
+    frame[30..32].copy_from_slice(&count.to_le_bytes());
+
+    for (segment_type, size, data) in segments {
+        frame.extend_from_slice(&size.to_le_bytes());
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(&segment_type.to_le_bytes());
+        frame.extend_from_slice(&[0; 2]);
+        frame.extend_from_slice(data);
+    }
+    frame
+}
+
+// the lobby drops the connection without taking anything else down with it
+async fn survives(buf: &[u8]) {
+    let config = Arc::new(Config::default());
+    let db = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let worlds = WorldRegistry::new(config.clone());
+
+    let (mut ours, theirs) = duplex(0x10000);
+    let mut client = Client::new(theirs, db, config, worlds, Connections::default());
+    let handle = tokio::spawn(async move { client.handle().await });
+
+    ours.write_all(buf).await.unwrap();
+    ours.shutdown().await.unwrap();
+
+    // whatever it answers with, it has to get to the end of the stream and hang up
+    let mut rest = Vec::new();
+    ours.read_to_end(&mut rest).await.unwrap();
+    handle.await.expect("client task panicked");
+}
+
+#[tokio::test]
+async fn short_encryption_init_closes_the_connection() {
+    survives(&frame(
+        1,
+        &[(SegmentType::EncryptionInit as u16, 20, &[0; 4])],
+    ))
+    .await;
+}
+
+#[tokio::test]
+async fn short_ipc_before_encryption_init() {
+    survives(&frame(1, &[(SegmentType::Ipc as u16, 18, &[0; 2])])).await;
+}
+
+#[tokio::test]
+async fn short_ipc_after_encryption_init() {
+    survives(&frame(
+        2,
+        &[
+            (SegmentType::EncryptionInit as u16, 16 + 0x280, &[0; 0x280]),
+            (SegmentType::Ipc as u16, 18, &[0; 2]),
+        ],
+    ))
+    .await;
+}
+
+#[tokio::test]
+async fn oversized_frame_closes_the_connection() {
+    let mut buf = frame(1, &[(SegmentType::Ipc as u16, 16 + 8, &[0; 8])]);
+    buf[24..28].copy_from_slice(&u32::MAX.to_le_bytes());
+    survives(&buf).await;
+}
+
+#[tokio::test]
+async fn underflowing_segment_size_closes_the_connection() {
+    survives(&frame(1, &[(SegmentType::Ipc as u16, 0, &[])])).await;
+}
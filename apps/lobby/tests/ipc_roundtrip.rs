@@ -0,0 +1,86 @@
+// every lobby IPC is read from random bytes, written and read back. their fields are all plain
+// data, so anything that isn't padding should survive, and nothing should move
+use binrw::{BinRead, BinWrite};
+use character::NameError;
+use lobby::{
+    ipc::{
+        IPCCharCreate, IPCCharList, IPCCharacter, IPCClientVersionInfo, IPCEnterWorld,
+        IPCLobbyError, IPCReqCharDelete, IPCReqCharList, IPCReqEnterWorld, IPCRetainer,
+        IPCRetainerList, IPCServer, IPCServerList, IPCServiceAccount, IPCServiceIDInfo,
+    },
+    lobby_error::LobbyError,
+};
+use proptest::{collection::vec, prelude::*};
+use sapphire_protocol::ipc::WireSize;
+use std::io::Cursor;
+
+macro_rules! roundtrip {
+    ($($name:ident: $ty:ty = $size:expr,)*) => {
+        proptest! {
+            $(
+                #[test]
+                fn $name(bytes in vec(any::<u8>(), $size)) {
+                    prop_assert_eq!(<$ty>::SIZE, $size);
+
+                    let value = <$ty>::read(&mut Cursor::new(&bytes)).unwrap();
+                    let mut writer = Cursor::new(Vec::new());
+                    value.write_to(&mut writer).unwrap();
+                    let written = writer.into_inner();
+                    prop_assert_eq!(written.len(), $size);
+                    // padding is written as zeroes, whatever was read
+                    for (i, (&ours, &theirs)) in written.iter().zip(&bytes).enumerate() {
+                        prop_assert!(ours == theirs || ours == 0, "byte {:#x} moved", i);
+                    }
+
+                    let mut reader = Cursor::new(&written);
+                    let again = <$ty>::read(&mut reader).unwrap();
+                    prop_assert_eq!(reader.position() as usize, $size);
+                    let mut writer = Cursor::new(Vec::new());
+                    again.write_to(&mut writer).unwrap();
+                    prop_assert_eq!(writer.into_inner(), written);
+                }
+            )*
+        }
+    };
+}
+
+roundtrip! {
+    lobby_error: IPCLobbyError = 0x216,
+    client_version_info: IPCClientVersionInfo = 0xd2,
+    req_char_list: IPCReqCharList = 0x8,
+    req_char_delete: IPCReqCharDelete = 0x3c,
+    req_enter_world: IPCReqEnterWorld = 0x10,
+    service_account: IPCServiceAccount = 0x50,
+    service_id_info: IPCServiceIDInfo = 0x28d,
+    server: IPCServer = 0x54,
+    server_list: IPCServerList = 0x210,
+    character: IPCCharacter = 0x49f,
+    char_list: IPCCharList = 0x99a,
+    retainer: IPCRetainer = 0x38,
+    retainer_list: IPCRetainerList = 0x208,
+    char_create: IPCCharCreate = 0x8c,
+    enter_world: IPCEnterWorld = 0xa0,
+}
+
+#[test]
+fn lobby_errors_for_names_each_get_their_own_message() {
+    let errors = [
+        NameError::Parts,
+        NameError::Length,
+        NameError::Characters,
+        NameError::Capitalisation,
+        NameError::Punctuation,
+        NameError::BannedWord,
+        NameError::Reserved,
+        NameError::Taken,
+    ];
+
+    let mut message_ids = Vec::new();
+    for e in errors {
+        let ipc = IPCLobbyError::new(7, e.into());
+        assert_eq!((ipc.seq, ipc.error_id), (7, 3006));
+        assert!(!message_ids.contains(&ipc.message_id), "{:?}", e);
+        message_ids.push(ipc.message_id);
+    }
+    assert_eq!(LobbyError::from(NameError::Taken).message_id(), 13004);
+}
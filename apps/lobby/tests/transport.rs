@@ -0,0 +1,117 @@
+use binrw::{BinRead, BinWrite};
+use lobby::{client::Client, config::Config, connections::Connections, worlds::WorldRegistry};
+use sapphire_protocol::packets::{KeepAlive, PacketHeader, PacketSegmentHeader, SegmentType};
+use std::{io::Cursor, mem::size_of, sync::Arc};
+use storage::SqliteStorage;
+use tokio::io::{duplex, AsyncReadExt, AsyncWriteExt, DuplexStream};
+
+fn frame(segment_type: SegmentType, data: &[u8]) -> Vec<u8> {
+    let size = size_of::<PacketHeader>() + size_of::<PacketSegmentHeader>() + data.len();
+    let header = PacketHeader {
+        unknown_0: 0,
+        unknown_8: 0,
+        timestamp: 0,
+        size: size as u32,
+        connection_type: 0,
+        count: 1,
+        unknown_20: 1,
+        is_compressed: 0,
+        unknown_24: 0,
+        uncompressed_size: 0,
+    };
+
+    let mut cursor = Cursor::new(Vec::new());
+    header.write_to(&mut cursor).unwrap();
+    PacketSegmentHeader::new(segment_type as u16, data.len() as u32, 0, 0)
+        .write_to(&mut cursor)
+        .unwrap();
+    cursor
+        .into_inner()
+        .into_iter()
+        .chain(data.iter().copied())
+        .collect()
+}
+
+#[tokio::test]
+async fn answers_keepalives_over_a_duplex() {
+    let (mut ours, theirs) = duplex(4096);
+    let config = Arc::new(Config::default());
+    let db = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let worlds = WorldRegistry::new(config.clone());
+
+    let mut client = Client::new(theirs, db, config, worlds, Connections::default());
+    tokio::spawn(async move { client.handle().await });
+
+    let mut keepalive = Cursor::new(Vec::new());
+    KeepAlive {
+        id: 7,
+        timestamp: 1234,
+    }
+    .write_to(&mut keepalive)
+    .unwrap();
+    ours.write_all(&frame(SegmentType::KeepAlive, keepalive.get_ref()))
+        .await
+        .unwrap();
+
+    let mut buf = vec![0; 4096];
+    let n = ours.read(&mut buf).await.unwrap();
+    let mut reply = Cursor::new(&buf[..n]);
+
+    let header = PacketHeader::read(&mut reply).unwrap();
+    assert_eq!(header.count, 1);
+    assert_eq!(header.size as usize, n);
+
+    let segment = PacketSegmentHeader::read(&mut reply).unwrap();
+    assert_eq!(segment.segment_type, SegmentType::KeepAliveResponse as u16);
+
+    let echoed = KeepAlive::read(&mut reply).unwrap();
+    assert_eq!((echoed.id, echoed.timestamp), (7, 1234));
+}
+
+fn keepalive_frame(id: u32) -> Vec<u8> {
+    let mut keepalive = Cursor::new(Vec::new());
+    KeepAlive {
+        id,
+        timestamp: 1234,
+    }
+    .write_to(&mut keepalive)
+    .unwrap();
+    frame(SegmentType::KeepAlive, keepalive.get_ref())
+}
+
+async fn read_keepalive_response(stream: &mut DuplexStream) -> KeepAlive {
+    let mut buf = vec![0; size_of::<PacketHeader>() + size_of::<PacketSegmentHeader>() + 8];
+    stream.read_exact(&mut buf).await.unwrap();
+    let mut reply = Cursor::new(&buf);
+
+    PacketHeader::read(&mut reply).unwrap();
+    let segment = PacketSegmentHeader::read(&mut reply).unwrap();
+    assert_eq!(segment.segment_type, SegmentType::KeepAliveResponse as u16);
+    KeepAlive::read(&mut reply).unwrap()
+}
+
+#[tokio::test]
+async fn frames_are_found_however_the_reads_split_them() {
+    let (mut ours, theirs) = duplex(4096);
+    let config = Arc::new(Config::default());
+    let db = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let worlds = WorldRegistry::new(config.clone());
+
+    let mut client = Client::new(theirs, db, config, worlds, Connections::default());
+    tokio::spawn(async move { client.handle().await });
+
+    // one frame in two halves
+    let first = keepalive_frame(1);
+    ours.write_all(&first[0..20]).await.unwrap();
+    ours.flush().await.unwrap();
+    tokio::task::yield_now().await;
+    ours.write_all(&first[20..]).await.unwrap();
+    assert_eq!(read_keepalive_response(&mut ours).await.id, 1);
+
+    // two frames in one write
+    let mut both = keepalive_frame(2);
+    both.extend_from_slice(&keepalive_frame(3));
+    ours.write_all(&both).await.unwrap();
+    assert_eq!(read_keepalive_response(&mut ours).await.id, 2);
+    assert_eq!(read_keepalive_response(&mut ours).await.id, 3);
+}
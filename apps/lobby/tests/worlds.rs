@@ -0,0 +1,83 @@
+use lobby::{
+    config::{Config, WorldConfig},
+    worlds::{
+        listen_for_heartbeats, WorldHeartbeat, WorldRegistry, WorldStatus,
+        HEARTBEAT_CLOSED_FOR_CREATION, HEARTBEAT_MAINTENANCE,
+    },
+};
+use std::{sync::Arc, time::Duration};
+use tokio::{net::UdpSocket, time};
+
+fn registry() -> WorldRegistry {
+    WorldRegistry::new(Arc::new(Config {
+        worlds: vec![WorldConfig {
+            id: 1,
+            name: "Ultros".to_string(),
+            data_centre: "Sapphire".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 54992,
+            congested_at: 500,
+            capacity: 600,
+            closed_for_creation: false,
+        }],
+        ..Config::default()
+    }))
+}
+
+fn status(registry: &WorldRegistry, flags: u16, population: u32) -> WorldStatus {
+    registry.heartbeat(&WorldHeartbeat {
+        world_id: 1,
+        flags,
+        population,
+    });
+    registry.world(1).unwrap().status
+}
+
+#[test]
+fn congested_and_closed_are_independent() {
+    let registry = registry();
+    assert_eq!(registry.world(1).unwrap().status.flags(), 0x00);
+
+    assert_eq!(status(&registry, 0, 10).flags(), 0x01);
+    assert_eq!(status(&registry, 0, 500).flags(), 0x01 | 0x02);
+    assert_eq!(
+        status(&registry, HEARTBEAT_CLOSED_FOR_CREATION, 10).flags(),
+        0x01 | 0x04
+    );
+
+    let both = status(&registry, HEARTBEAT_CLOSED_FOR_CREATION, 500);
+    assert!(both.congested && both.closed_for_creation);
+    assert_eq!(both.flags(), 0x01 | 0x02 | 0x04);
+
+    let down = status(
+        &registry,
+        HEARTBEAT_MAINTENANCE | HEARTBEAT_CLOSED_FOR_CREATION,
+        500,
+    );
+    assert!(down.maintenance);
+    assert_eq!(down.flags(), 0x00);
+}
+
+#[tokio::test]
+async fn heartbeats_arrive_over_udp() {
+    let registry = registry();
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let address = socket.local_addr().unwrap();
+    tokio::spawn(listen_for_heartbeats(registry.clone(), socket));
+
+    let world = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let mut heartbeat = Vec::new();
+    heartbeat.extend_from_slice(&1u16.to_le_bytes());
+    heartbeat.extend_from_slice(&0u16.to_le_bytes());
+    heartbeat.extend_from_slice(&42u32.to_le_bytes());
+    world.send_to(&heartbeat, address).await.unwrap();
+
+    for _ in 0..50 {
+        if registry.world(1).unwrap().population == 42 {
+            assert!(!registry.world(1).unwrap().status.maintenance);
+            return;
+        }
+        time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("the heartbeat never arrived");
+}
@@ -0,0 +1,306 @@
+pub mod net;
+pub mod pcap;
+pub mod stream;
+
+use lobby::ipc::IPCClientVersionInfo;
+use net::parse_tcp;
+use pcap::Packet;
+use sapphire_protocol::{
+    encryption::{decrypt_ipc, derive_key, encryption_init_key},
+    ipc,
+    opcodes::OpcodeTables,
+    packets::{frame_size, parse_frame, FrameLimits, PacketRaw, SegmentType},
+};
+use std::{collections::HashMap, net::SocketAddr, time::Duration};
+use stream::Reassembler;
+
+// far looser than the lobby's, anything bigger is us being out of step with the stream rather
+// than a real frame
+const LIMITS: FrameLimits = FrameLimits {
+    max_frame_size: 0x100000,
+    max_segments: u16::MAX,
+    max_ipc_size: 0x100000,
+};
+
+pub struct Segment {
+    pub timestamp: Duration,
+    // the client's end of the connection, to tell connections apart
+    pub client: SocketAddr,
+    pub from_client: bool,
+    pub segment_type: u16,
+    // false for IPCs we couldn't find a key for, which are left as they came
+    pub decrypted: bool,
+    pub data: Vec<u8>,
+    // what the opcode means in the connection's game version, if we know
+    pub name: Option<String>,
+}
+
+impl Segment {
+    pub fn direction(&self) -> ipc::Direction {
+        match self.from_client {
+            true => ipc::Direction::ClientToServer,
+            false => ipc::Direction::ServerToClient,
+        }
+    }
+
+    pub fn opcode(&self) -> Option<u16> {
+        match (self.segment_type == SegmentType::Ipc as u16, self.decrypted) {
+            (true, true) if self.data.len() >= 4 => {
+                Some(u16::from_le_bytes([self.data[2], self.data[3]]))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn opcode_name(&self) -> Option<String> {
+        self.opcode()?;
+        Some(self.name.clone().unwrap_or_else(|| "Unknown".to_string()))
+    }
+}
+
+pub enum Event {
+    Segment(Segment),
+    // something about the stream worth telling whoever's reading
+    Note {
+        timestamp: Duration,
+        client: SocketAddr,
+        text: String,
+    },
+}
+
+#[derive(Default)]
+struct Direction {
+    reassembler: Reassembler,
+    buffer: Vec<u8>,
+    // set once the stream stops making sense, after which it's ignored until a gap gives it
+    // somewhere new to start
+    lost: bool,
+}
+
+#[derive(Default)]
+struct Connection {
+    client: Direction,
+    server: Direction,
+    // derived from the EncryptionInit for every game version we were given
+    key_candidates: Vec<(u16, Vec<u8>)>,
+    key: Option<Vec<u8>>,
+    // the game version whose key worked, which decides what the opcodes mean
+    game_version: Option<u16>,
+}
+
+// follows every TCP connection to the lobby port in a capture and decodes what went over them
+pub struct Dissector {
+    port: u16,
+    // null padded, the way it sits in an EncryptionInit
+    key_phrase: [u8; 32],
+    game_versions: Vec<u16>,
+    opcode_tables: OpcodeTables,
+    connections: HashMap<(SocketAddr, SocketAddr), Connection>,
+}
+
+impl Dissector {
+    pub fn new(
+        port: u16,
+        key_phrase: &str,
+        game_versions: Vec<u16>,
+        opcode_tables: OpcodeTables,
+    ) -> Dissector {
+        let mut padded = [0; 32];
+        let len = key_phrase.len().min(32);
+        padded[0..len].copy_from_slice(&key_phrase.as_bytes()[0..len]);
+
+        Dissector {
+            port,
+            key_phrase: padded,
+            game_versions,
+            opcode_tables,
+            connections: HashMap::new(),
+        }
+    }
+
+    pub fn packet(&mut self, packet: &Packet) -> Vec<Event> {
+        let tcp = match parse_tcp(packet.link_type, &packet.data) {
+            Some(tcp) => tcp,
+            None => return Vec::new(),
+        };
+
+        let (client, server, from_client) = if tcp.destination.port() == self.port {
+            (tcp.source, tcp.destination, true)
+        } else if tcp.source.port() == self.port {
+            (tcp.destination, tcp.source, false)
+        } else {
+            return Vec::new();
+        };
+
+        let connection = self.connections.entry((client, server)).or_default();
+        let direction = match from_client {
+            true => &mut connection.client,
+            false => &mut connection.server,
+        };
+
+        let note = |text: String| Event::Note {
+            timestamp: packet.timestamp,
+            client,
+            text,
+        };
+        let mut events = Vec::new();
+
+        let pushed = direction.reassembler.push(tcp.seq, tcp.syn, &tcp.payload);
+        if let Some(skipped) = pushed.skipped {
+            // whatever frame we were partway through is gone, but frames tend to start at the
+            // beginning of a segment so there's a fair chance the next one starts here
+            events.push(note(format!("gap in stream, skipping {} bytes", skipped)));
+            direction.buffer.clear();
+            direction.lost = false;
+        }
+        if direction.lost || pushed.bytes.is_empty() {
+            return events;
+        }
+        direction.buffer.extend_from_slice(&pushed.bytes);
+
+        let frames = take_frames(direction);
+
+        for segments in frames {
+            let segments = match segments {
+                Ok(segments) => segments,
+                Err(e) => {
+                    events.push(note(e));
+                    continue;
+                }
+            };
+
+            for segment in segments {
+                let mut segment = Segment {
+                    timestamp: packet.timestamp,
+                    client,
+                    from_client,
+                    segment_type: segment.segment_header.segment_type,
+                    decrypted: false,
+                    data: segment.data,
+                    name: None,
+                };
+                if let Some(text) = self.decrypt(client, server, &mut segment) {
+                    events.push(note(text));
+                }
+                self.name(client, server, &mut segment);
+                events.push(Event::Segment(segment));
+            }
+        }
+
+        events
+    }
+
+    // decrypts IPCs in place, anything it returns is a note to show before the segment
+    fn decrypt(
+        &mut self,
+        client: SocketAddr,
+        server: SocketAddr,
+        segment: &mut Segment,
+    ) -> Option<String> {
+        let connection = self.connections.get_mut(&(client, server))?;
+
+        if segment.from_client && segment.segment_type == SegmentType::EncryptionInit as u16 {
+            let key = match encryption_init_key(&segment.data) {
+                Some((key, _)) => key,
+                None => return Some("EncryptionInit too short for its key".to_string()),
+            };
+            connection.key_candidates = self
+                .game_versions
+                .iter()
+                .map(|&v| (v, derive_key(key, &self.key_phrase, v)))
+                .collect();
+            connection.key = None;
+            connection.game_version = None;
+            return None;
+        }
+
+        if segment.segment_type != SegmentType::Ipc as u16 {
+            return None;
+        }
+
+        // the same trick the lobby uses: the client's first IPC is always a ClientVersionInfo
+        if connection.key.is_none() && segment.from_client && !connection.key_candidates.is_empty()
+        {
+            let tables = &self.opcode_tables;
+            let found = connection
+                .key_candidates
+                .iter()
+                .find(|(game_version, key)| {
+                    let expected = tables
+                        .get(*game_version)
+                        .and_then(|table| table.opcode_of::<IPCClientVersionInfo>());
+                    let data = decrypt_ipc(key, ipc::Direction::ClientToServer, &segment.data);
+                    expected.is_some()
+                        && data.len() >= 4
+                        && Some(u16::from_le_bytes([data[2], data[3]])) == expected
+                });
+            let note = match found {
+                Some((game_version, key)) => {
+                    connection.key = Some(key.clone());
+                    connection.game_version = Some(*game_version);
+                    format!("using the key for game version {}", game_version)
+                }
+                None => "no game version's key decrypts the first IPC, is the key phrase right?"
+                    .to_string(),
+            };
+            // one try per EncryptionInit, so a wrong key phrase only gets said once
+            connection.key_candidates.clear();
+
+            if let Some(key) = &connection.key {
+                segment.data = decrypt_ipc(key, segment.direction(), &segment.data);
+                segment.decrypted = true;
+            }
+            return Some(note);
+        }
+
+        if let Some(key) = &connection.key {
+            segment.data = decrypt_ipc(key, segment.direction(), &segment.data);
+            segment.decrypted = true;
+        }
+        None
+    }
+
+    // looks a decrypted IPC's opcode up in its connection's game version
+    fn name(&self, client: SocketAddr, server: SocketAddr, segment: &mut Segment) {
+        let opcode = match segment.opcode() {
+            Some(opcode) => opcode,
+            None => return,
+        };
+        let direction = segment.direction();
+        segment.name = self
+            .connections
+            .get(&(client, server))
+            .and_then(|connection| connection.game_version)
+            .and_then(|game_version| self.opcode_tables.get(game_version))
+            .and_then(|table| table.name(direction, opcode))
+            .map(str::to_string);
+    }
+}
+
+// pulls every whole frame off the front of a direction's buffer and splits it into segments
+fn take_frames(direction: &mut Direction) -> Vec<Result<Vec<PacketRaw>, String>> {
+    let mut frames = Vec::new();
+
+    loop {
+        let size = match frame_size(&direction.buffer, &LIMITS) {
+            Ok(Some(size)) if direction.buffer.len() >= size => size,
+            Ok(_) => break,
+            Err(e) => {
+                // there's no finding the next frame from here, so give up on this direction
+                frames.push(Err(format!("{}, ignoring the rest of this direction", e)));
+                direction.lost = true;
+                direction.buffer.clear();
+                break;
+            }
+        };
+
+        let frame: Vec<u8> = direction.buffer.drain(0..size).collect();
+        frames.push(
+            parse_frame(&frame, &LIMITS)
+                .map(|(_, segments)| segments)
+                .map_err(|e| format!("couldn't split frame: {}", e)),
+        );
+    }
+
+    frames
+}
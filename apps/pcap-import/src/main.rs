@@ -0,0 +1,100 @@
+use lobby::config::Config;
+use pcap_import::{pcap::read_packets, Dissector, Event};
+use sapphire_protocol::packets::SegmentType;
+use std::{env, error::Error, fs, process};
+
+const USAGE: &str = "usage:
+    pcap-import <capture> <key phrase> [port] [game version]";
+
+// the port the retail lobby listens on
+const DEFAULT_PORT: u16 = 54994;
+
+fn hex_dump(data: &[u8]) {
+    for (i, line) in data.chunks(16).enumerate() {
+        let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+        let text: String = line
+            .iter()
+            .map(|&b| match b {
+                0x20..=0x7e => b as char,
+                _ => '.',
+            })
+            .collect();
+        println!("    {:04x}  {:<48} {}", i * 16, hex.join(" "), text);
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.len() < 2 || args.len() > 4 {
+        println!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let port = match args.get(2) {
+        Some(port) => port.parse()?,
+        None => DEFAULT_PORT,
+    };
+    // the opcodes come from the lobby config either way
+    let config_path = env::var("LOBBY_CONFIG").unwrap_or_else(|_| "lobby.toml".to_string());
+    let config = Config::load(&config_path)?;
+    let game_versions = match args.get(3) {
+        Some(version) => vec![version.parse()?],
+        None => config
+            .client_versions
+            .iter()
+            .map(|v| v.game_version)
+            .collect(),
+    };
+
+    let packets = read_packets(&fs::read(&args[0])?)?;
+    let start = packets.first().map(|p| p.timestamp).unwrap_or_default();
+    let mut dissector = Dissector::new(port, &args[1], game_versions, config.opcode_tables);
+
+    for packet in &packets {
+        for event in dissector.packet(packet) {
+            match event {
+                Event::Note {
+                    timestamp,
+                    client,
+                    text,
+                } => {
+                    let elapsed = timestamp.saturating_sub(start).as_secs_f64();
+                    println!("{:>10.3} {} -- {}", elapsed, client, text);
+                }
+                Event::Segment(segment) => {
+                    let elapsed = segment.timestamp.saturating_sub(start).as_secs_f64();
+                    let arrow = match segment.from_client {
+                        true => "->",
+                        false => "<-",
+                    };
+                    let what = match (segment.opcode(), segment.opcode_name()) {
+                        (Some(opcode), Some(name)) => format!("IPC {:#06x} {}", opcode, name),
+                        _ => segment_name(segment.segment_type),
+                    };
+
+                    println!(
+                        "{:>10.3} {} {} {} ({:#x} bytes)",
+                        elapsed,
+                        segment.client,
+                        arrow,
+                        what,
+                        segment.data.len()
+                    );
+                    if segment.opcode().is_some() {
+                        hex_dump(&segment.data);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn segment_name(segment_type: u16) -> String {
+    match SegmentType::try_from(segment_type) {
+        Ok(SegmentType::Ipc) => "IPC (encrypted)".to_string(),
+        Ok(t) => format!("{:?}", t),
+        Err(_) => format!("segment type {:#x}", segment_type),
+    }
+}
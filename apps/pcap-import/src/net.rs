@@ -0,0 +1,101 @@
+use crate::pcap::{
+    LINKTYPE_ETHERNET, LINKTYPE_IPV4, LINKTYPE_IPV6, LINKTYPE_LINUX_SLL, LINKTYPE_NULL,
+    LINKTYPE_RAW,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const PROTOCOL_TCP: u8 = 6;
+
+const TCP_SYN: u8 = 0x02;
+
+pub struct TcpSegment {
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub seq: u32,
+    pub syn: bool,
+    pub payload: Vec<u8>,
+}
+
+fn be16(data: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        data.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn be32(data: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        data.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+// anything that isn't TCP over IP, or is cut short, is None
+pub fn parse_tcp(link_type: u32, data: &[u8]) -> Option<TcpSegment> {
+    let ip = match link_type {
+        LINKTYPE_ETHERNET => {
+            let mut offset = 12;
+            let mut ethertype = be16(data, offset)?;
+            while ethertype == ETHERTYPE_VLAN {
+                offset += 4;
+                ethertype = be16(data, offset)?;
+            }
+            match ethertype {
+                ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => data.get(offset + 2..)?,
+                _ => return None,
+            }
+        }
+        LINKTYPE_LINUX_SLL => data.get(16..)?,
+        // the address family is in the capturing machine's byte order, so just trust the version
+        LINKTYPE_NULL => data.get(4..)?,
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 => data,
+        _ => return None,
+    };
+
+    let (source, destination, tcp) = match ip.first()? >> 4 {
+        4 => {
+            let header_len = ((ip[0] & 0x0f) as usize) * 4;
+            let total_len = be16(ip, 2)? as usize;
+            if *ip.get(9)? != PROTOCOL_TCP {
+                return None;
+            }
+
+            let source: [u8; 4] = ip.get(12..16)?.try_into().ok()?;
+            let destination: [u8; 4] = ip.get(16..20)?.try_into().ok()?;
+            // ethernet pads short frames, the IP header knows where the packet really ends
+            let tcp = ip.get(header_len..total_len.min(ip.len()))?;
+            (
+                IpAddr::V4(Ipv4Addr::from(source)),
+                IpAddr::V4(Ipv4Addr::from(destination)),
+                tcp,
+            )
+        }
+        6 => {
+            // extension headers don't show up in lobby traffic, so don't bother with them
+            if *ip.get(6)? != PROTOCOL_TCP {
+                return None;
+            }
+            let payload_len = be16(ip, 4)? as usize;
+
+            let source: [u8; 16] = ip.get(8..24)?.try_into().ok()?;
+            let destination: [u8; 16] = ip.get(24..40)?.try_into().ok()?;
+            let tcp = ip.get(40..(40 + payload_len).min(ip.len()))?;
+            (
+                IpAddr::V6(Ipv6Addr::from(source)),
+                IpAddr::V6(Ipv6Addr::from(destination)),
+                tcp,
+            )
+        }
+        _ => return None,
+    };
+
+    let header_len = ((*tcp.get(12)? >> 4) as usize) * 4;
+    Some(TcpSegment {
+        source: SocketAddr::new(source, be16(tcp, 0)?),
+        destination: SocketAddr::new(destination, be16(tcp, 2)?),
+        seq: be32(tcp, 4)?,
+        syn: tcp.get(13)? & TCP_SYN != 0,
+        payload: tcp.get(header_len..)?.to_vec(),
+    })
+}
@@ -0,0 +1,240 @@
+use std::{error::Error, time::Duration};
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+pub const LINKTYPE_NULL: u32 = 0;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_RAW: u32 = 101;
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+pub const LINKTYPE_IPV4: u32 = 228;
+pub const LINKTYPE_IPV6: u32 = 229;
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b23c4d;
+
+const BLOCK_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const BLOCK_INTERFACE_DESCRIPTION: u32 = 1;
+const BLOCK_SIMPLE_PACKET: u32 = 3;
+const BLOCK_ENHANCED_PACKET: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+const OPTION_END: u16 = 0;
+const OPTION_IF_TSRESOL: u16 = 9;
+
+pub struct Packet {
+    // since the unix epoch, as the capturing machine saw it
+    pub timestamp: Duration,
+    pub link_type: u32,
+    pub data: Vec<u8>,
+}
+
+// both pcap flavours come in either byte order, so everything goes through this
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big_endian: bool,
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or("capture ends in the middle of a block")?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        let bytes = self.bytes(2)?.try_into().expect("asked for two bytes");
+        Ok(match self.big_endian {
+            true => u16::from_be_bytes(bytes),
+            false => u16::from_le_bytes(bytes),
+        })
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        let bytes = self.bytes(4)?.try_into().expect("asked for four bytes");
+        Ok(match self.big_endian {
+            true => u32::from_be_bytes(bytes),
+            false => u32::from_le_bytes(bytes),
+        })
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.data.len()
+    }
+}
+
+// reads a whole pcap or pcapng file, whichever it turns out to be
+pub fn read_packets(data: &[u8]) -> Result<Vec<Packet>> {
+    let magic = data.get(0..4).ok_or("too short to be a capture")?;
+    let magic = u32::from_le_bytes(magic.try_into().expect("asked for four bytes"));
+
+    if magic == BLOCK_SECTION_HEADER {
+        return read_pcapng(data);
+    }
+
+    for big_endian in [false, true] {
+        let magic = match big_endian {
+            true => magic.swap_bytes(),
+            false => magic,
+        };
+        if magic == PCAP_MAGIC_MICROS || magic == PCAP_MAGIC_NANOS {
+            return read_pcap(data, big_endian, magic == PCAP_MAGIC_NANOS);
+        }
+    }
+
+    Err("not a pcap or pcapng file".into())
+}
+
+fn read_pcap(data: &[u8], big_endian: bool, nanos: bool) -> Result<Vec<Packet>> {
+    let mut reader = Reader {
+        data,
+        pos: 20,
+        big_endian,
+    };
+    let link_type = reader.u32()?;
+
+    let mut packets = Vec::new();
+    while !reader.is_empty() {
+        let seconds = reader.u32()? as u64;
+        let fraction = reader.u32()?;
+        let captured_len = reader.u32()? as usize;
+        let _original_len = reader.u32()?;
+
+        let fraction = match nanos {
+            true => Duration::from_nanos(fraction as u64),
+            false => Duration::from_micros(fraction as u64),
+        };
+        packets.push(Packet {
+            timestamp: Duration::from_secs(seconds) + fraction,
+            link_type,
+            data: reader.bytes(captured_len)?.to_vec(),
+        });
+    }
+
+    Ok(packets)
+}
+
+struct Interface {
+    link_type: u32,
+    // timestamp units per second
+    resolution: u64,
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Vec<Packet>> {
+    let mut reader = Reader {
+        data,
+        pos: 0,
+        big_endian: false,
+    };
+    let mut interfaces: Vec<Interface> = Vec::new();
+    let mut packets = Vec::new();
+
+    while !reader.is_empty() {
+        let start = reader.pos;
+        let block_type = reader.u32()?;
+
+        // every section says its own byte order, and starts its own list of interfaces
+        if block_type == BLOCK_SECTION_HEADER {
+            let _len = reader.bytes(4)?;
+            let magic = reader.bytes(4)?;
+            reader.big_endian = match u32::from_le_bytes(magic.try_into().expect("four bytes")) {
+                BYTE_ORDER_MAGIC => false,
+                m if m.swap_bytes() == BYTE_ORDER_MAGIC => true,
+                _ => return Err("bad pcapng byte order magic".into()),
+            };
+            reader.pos = start + 4;
+            interfaces.clear();
+        }
+
+        let len = reader.u32()? as usize;
+        if len < 12 || len % 4 != 0 {
+            return Err(format!("bad pcapng block length {} at {:#x}", len, start).into());
+        }
+        let mut body = Reader {
+            data: reader.bytes(len - 12)?,
+            pos: 0,
+            big_endian: reader.big_endian,
+        };
+        reader.u32()?;
+
+        match block_type {
+            BLOCK_INTERFACE_DESCRIPTION => {
+                let link_type = body.u16()? as u32;
+                body.bytes(6)?;
+                interfaces.push(Interface {
+                    link_type,
+                    resolution: read_resolution(&mut body)?,
+                });
+            }
+            BLOCK_ENHANCED_PACKET => {
+                let interface = body.u32()? as usize;
+                let interface = interfaces
+                    .get(interface)
+                    .ok_or("packet on an interface that was never described")?;
+                let timestamp = ((body.u32()? as u64) << 32) | body.u32()? as u64;
+                let captured_len = body.u32()? as usize;
+                let _original_len = body.u32()?;
+
+                packets.push(Packet {
+                    timestamp: to_duration(timestamp, interface.resolution),
+                    link_type: interface.link_type,
+                    data: body.bytes(captured_len)?.to_vec(),
+                });
+            }
+            BLOCK_SIMPLE_PACKET => {
+                let interface = interfaces
+                    .first()
+                    .ok_or("packet on an interface that was never described")?;
+                let original_len = body.u32()? as usize;
+                let captured_len = original_len.min(body.data.len() - 4);
+
+                packets.push(Packet {
+                    timestamp: Duration::ZERO,
+                    link_type: interface.link_type,
+                    data: body.bytes(captured_len)?.to_vec(),
+                });
+            }
+            // statistics, name resolution and whatever else, none of it matters here
+            _ => (),
+        }
+    }
+
+    Ok(packets)
+}
+
+// microseconds unless the interface says otherwise
+fn read_resolution(options: &mut Reader) -> Result<u64> {
+    let mut resolution = 1_000_000;
+
+    while options.data.len() - options.pos >= 4 {
+        let code = options.u16()?;
+        let len = options.u16()? as usize;
+        if code == OPTION_END {
+            break;
+        }
+
+        let value = options.bytes(len)?;
+        options.bytes((4 - len % 4) % 4)?;
+
+        if code == OPTION_IF_TSRESOL && len == 1 {
+            // the high bit picks a power of two instead of a power of ten
+            let exponent = (value[0] & 0x7f) as u32;
+            resolution = match value[0] & 0x80 {
+                0 => 10_u64.checked_pow(exponent),
+                _ => 2_u64.checked_pow(exponent),
+            }
+            .ok_or("timestamp resolution too fine")?;
+        }
+    }
+
+    Ok(resolution)
+}
+
+fn to_duration(timestamp: u64, resolution: u64) -> Duration {
+    let seconds = timestamp / resolution;
+    let fraction = timestamp % resolution;
+    Duration::from_secs(seconds)
+        + Duration::from_nanos((fraction as u128 * 1_000_000_000 / resolution as u128) as u64)
+}
@@ -0,0 +1,87 @@
+// how many segments can be waiting on a gap before we decide whatever fills it was never
+// captured. retransmissions turn up well before this many more segments have gone past
+pub const MAX_PENDING: usize = 128;
+
+// puts one direction of a TCP connection back in order. sequence numbers wrap, so they're only
+// ever compared by how far apart they are
+#[derive(Default)]
+pub struct Reassembler {
+    next: Option<u32>,
+    // arrived before the bytes in front of them
+    pending: Vec<(u32, Vec<u8>)>,
+}
+
+#[derive(Default)]
+pub struct Pushed {
+    // bytes given up on before `bytes`, which then start wherever the stream picked up again
+    pub skipped: Option<u32>,
+    pub bytes: Vec<u8>,
+}
+
+// how far ahead of `next` the sequence number is, negative when it's behind
+fn distance(seq: u32, next: u32) -> i32 {
+    seq.wrapping_sub(next) as i32
+}
+
+impl Reassembler {
+    // returns whatever this segment made contiguous
+    pub fn push(&mut self, seq: u32, syn: bool, payload: &[u8]) -> Pushed {
+        // the SYN takes up a sequence number of its own
+        let seq = match syn {
+            true => seq.wrapping_add(1),
+            false => seq,
+        };
+        // a capture started mid-connection begins wherever we first see data
+        let next = *self.next.get_or_insert(seq);
+
+        let mut out = Pushed::default();
+        if payload.is_empty() || distance(seq, next) > 0 {
+            if !payload.is_empty() {
+                self.pending.push((seq, payload.to_vec()));
+            }
+            if self.pending.len() <= MAX_PENDING {
+                return out;
+            }
+
+            // the gap isn't getting filled, so carry on from the first segment after it
+            let resume = self
+                .pending
+                .iter()
+                .map(|(seq, _)| *seq)
+                .min_by_key(|seq| distance(*seq, next))
+                .expect("over the limit, so not empty");
+            out.skipped = Some(distance(resume, next) as u32);
+            self.next = Some(resume);
+        } else {
+            self.take(seq, payload, &mut out.bytes);
+        }
+
+        // anything waiting that now lines up
+        loop {
+            let next = self.next.expect("set above");
+            let ready = self
+                .pending
+                .iter()
+                .position(|(seq, _)| distance(*seq, next) <= 0);
+            match ready {
+                Some(i) => {
+                    let (seq, payload) = self.pending.swap_remove(i);
+                    self.take(seq, &payload, &mut out.bytes);
+                }
+                None => break,
+            }
+        }
+
+        out
+    }
+
+    // appends the part of a segment at or behind `next` that we haven't had yet
+    fn take(&mut self, seq: u32, payload: &[u8], out: &mut Vec<u8>) {
+        let next = self.next.expect("set before taking");
+        let seen = (-distance(seq, next)) as usize;
+        if seen < payload.len() {
+            out.extend_from_slice(&payload[seen..]);
+            self.next = Some(seq.wrapping_add(payload.len() as u32));
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use fake_client::FakeClient;
+use lobby::config::Config;
+use pcap_import::{pcap::read_packets, stream::MAX_PENDING, Dissector, Event};
+use sapphire_protocol::{
+    encryption::{derive_key, encrypt_ipc, encryption_init_key},
+    ipc::Direction,
+};
+use tokio::io::{duplex, AsyncReadExt, DuplexStream};
+
+const KEY_PHRASE: &str = "test key phrase";
+const KEY: u32 = 0xdeadbeef;
+const GAME_VERSION: u16 = 6100;
+const SESSION_ID: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789ab";
+const LOBBY_PORT: u16 = 54994;
+const CLIENT_PORT: u16 = 50123;
+
+// frame header plus the one segment header the fake client sends
+const FRAME_OVERHEAD: usize = 40 + 16;
+
+async fn read_frame(stream: &mut DuplexStream) -> Vec<u8> {
+    let mut frame = vec![0; 40];
+    stream.read_exact(&mut frame).await.unwrap();
+    let size = u32::from_le_bytes(frame[24..28].try_into().unwrap()) as usize;
+    frame.resize(size, 0);
+    stream.read_exact(&mut frame[40..]).await.unwrap();
+    frame
+}
+
+// what a client would have sent: an EncryptionInit, then an encrypted ClientVersionInfo
+async fn client_frames() -> (Vec<u8>, Vec<u8>) {
+    let (ours, mut theirs) = duplex(0x10000);
+    let mut client = FakeClient::new(ours, GAME_VERSION);
+
+    let mut init = vec![0; 0x280];
+    init[36..36 + KEY_PHRASE.len()].copy_from_slice(KEY_PHRASE.as_bytes());
+    init[100..104].copy_from_slice(&KEY.to_le_bytes());
+    client.send_segment(9, &init).await.unwrap();
+    let init = read_frame(&mut theirs).await;
+
+    // sent without a key, then encrypted the way it would have been
+    client
+        .client_version_info(1, SESSION_ID, "2022.05.19.0000.0000")
+        .await
+        .unwrap();
+    let mut version_info = read_frame(&mut theirs).await;
+    let (key, key_phrase) = encryption_init_key(&init[FRAME_OVERHEAD..]).unwrap();
+    let key = derive_key(key, key_phrase, GAME_VERSION);
+    let encrypted = encrypt_ipc(
+        &key,
+        Direction::ClientToServer,
+        &version_info[FRAME_OVERHEAD..],
+    );
+    version_info[FRAME_OVERHEAD..].copy_from_slice(&encrypted);
+
+    (init, version_info)
+}
+
+async fn server_frame() -> Vec<u8> {
+    let (ours, mut theirs) = duplex(0x10000);
+    let mut server = FakeClient::new(ours, GAME_VERSION);
+    server.send_segment(0x0a, &[0; 0x290]).await.unwrap();
+    read_frame(&mut theirs).await
+}
+
+fn block(out: &mut Vec<u8>, block_type: u32, body: &[u8]) {
+    let padding = (4 - body.len() % 4) % 4;
+    let len = (12 + body.len() + padding) as u32;
+    out.extend_from_slice(&block_type.to_le_bytes());
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(body);
+    out.resize(out.len() + padding, 0);
+    out.extend_from_slice(&len.to_le_bytes());
+}
+
+fn tcp_packet(from_client: bool, seq: u32, syn: bool, payload: &[u8]) -> Vec<u8> {
+    let (client, server) = ([10, 0, 0, 2], [10, 0, 0, 1]);
+    let (source, destination, source_port, destination_port) = match from_client {
+        true => (client, server, CLIENT_PORT, LOBBY_PORT),
+        false => (server, client, LOBBY_PORT, CLIENT_PORT),
+    };
+
+    // ethernet
+    let mut packet = vec![0; 12];
+    packet.extend_from_slice(&0x0800_u16.to_be_bytes());
+
+    // ipv4
+    packet.extend_from_slice(&[0x45, 0]);
+    packet.extend_from_slice(&((20 + 20 + payload.len()) as u16).to_be_bytes());
+    packet.extend_from_slice(&[0, 0, 0x40, 0, 64, 6, 0, 0]);
+    packet.extend_from_slice(&source);
+    packet.extend_from_slice(&destination);
+
+    // tcp
+    packet.extend_from_slice(&source_port.to_be_bytes());
+    packet.extend_from_slice(&destination_port.to_be_bytes());
+    packet.extend_from_slice(&seq.to_be_bytes());
+    packet.extend_from_slice(&[0; 4]);
+    packet.extend_from_slice(&[0x50, if syn { 0x02 } else { 0x18 }]);
+    packet.extend_from_slice(&[0xff, 0xff, 0, 0, 0, 0]);
+
+    packet.extend_from_slice(payload);
+    packet
+}
+
+// a section, one ethernet interface with nanosecond timestamps, and a packet a second
+fn pcapng(packets: &[Vec<u8>]) -> Vec<u8> {
+    let mut out = Vec::new();
+
+    let mut section = Vec::new();
+    section.extend_from_slice(&0x1a2b3c4d_u32.to_le_bytes());
+    section.extend_from_slice(&[1, 0, 0, 0]);
+    section.extend_from_slice(&(-1_i64).to_le_bytes());
+    block(&mut out, 0x0a0d0d0a, &section);
+
+    let mut interface = Vec::new();
+    interface.extend_from_slice(&1_u16.to_le_bytes());
+    interface.extend_from_slice(&[0; 6]);
+    interface.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0]);
+    interface.extend_from_slice(&[0; 4]);
+    block(&mut out, 1, &interface);
+
+    for (i, packet) in packets.iter().enumerate() {
+        let timestamp = (1_655_000_000 + i as u64) * 1_000_000_000;
+        let mut body = Vec::new();
+        body.extend_from_slice(&0_u32.to_le_bytes());
+        body.extend_from_slice(&((timestamp >> 32) as u32).to_le_bytes());
+        body.extend_from_slice(&(timestamp as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(&(packet.len() as u32).to_le_bytes());
+        body.extend_from_slice(packet);
+        block(&mut out, 6, &body);
+    }
+
+    out
+}
+
+async fn capture() -> Vec<u8> {
+    let (init, version_info) = client_frames().await;
+    let reply = server_frame().await;
+    let (first, second) = version_info.split_at(100);
+    let version_info_seq = 1001 + init.len() as u32;
+
+    pcapng(&[
+        tcp_packet(true, 1000, true, &[]),
+        tcp_packet(false, 5000, true, &[]),
+        tcp_packet(true, 1001, false, &init),
+        tcp_packet(false, 5001, false, &reply),
+        // the rest of the ClientVersionInfo overtakes its start, which then gets sent twice
+        tcp_packet(true, version_info_seq + first.len() as u32, false, second),
+        tcp_packet(true, version_info_seq, false, first),
+        tcp_packet(true, version_info_seq, false, first),
+    ])
+}
+
+fn dissect(capture: &[u8], key_phrase: &str) -> Vec<Event> {
+    let packets = read_packets(capture).unwrap();
+    let opcodes = Config::default().opcode_tables;
+    let mut dissector = Dissector::new(LOBBY_PORT, key_phrase, vec![6000, GAME_VERSION], opcodes);
+    packets.iter().flat_map(|p| dissector.packet(p)).collect()
+}
+
+#[tokio::test]
+async fn rebuilds_streams_and_decrypts_ipcs() {
+    let capture = capture().await;
+    let packets = read_packets(&capture).unwrap();
+    assert_eq!(packets.len(), 7);
+    assert_eq!(packets[1].timestamp.as_secs(), 1_655_000_001);
+
+    let events = dissect(&capture, KEY_PHRASE);
+
+    let summary: Vec<String> = events
+        .iter()
+        .map(|event| match event {
+            Event::Segment(segment) => format!(
+                "{} {} {:?}",
+                segment.from_client,
+                segment.segment_type,
+                segment.opcode_name()
+            ),
+            Event::Note { text, .. } => text.clone(),
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            "true 9 None",
+            "false 10 None",
+            "using the key for game version 6100",
+            "true 3 Some(\"ClientVersionInfo\")",
+        ]
+    );
+
+    let version_info = match &events[3] {
+        Event::Segment(segment) => segment,
+        Event::Note { .. } => unreachable!(),
+    };
+    assert_eq!(version_info.timestamp.as_secs(), 1_655_000_005);
+    // padded out to whole blocks
+    assert_eq!(version_info.data.len(), 0x10 + 8 + 10 + 0x40 + 0x80 + 6);
+    assert!(version_info
+        .data
+        .windows(SESSION_ID.len())
+        .any(|w| w == SESSION_ID.as_bytes()));
+}
+
+#[tokio::test]
+async fn a_wrong_key_phrase_leaves_ipcs_encrypted() {
+    let events = dissect(&capture().await, "not the key phrase");
+
+    let last = events.iter().rev().take(2).collect::<Vec<_>>();
+    match last[..] {
+        [Event::Segment(segment), Event::Note { text, .. }] => {
+            assert!(!segment.decrypted);
+            assert_eq!(segment.opcode_name(), None);
+            assert!(text.starts_with("no game version's key"));
+        }
+        _ => panic!("expected a note then the still encrypted IPC"),
+    }
+}
+
+#[tokio::test]
+async fn segments_missing_from_the_capture_are_skipped() {
+    let (init, version_info) = client_frames().await;
+    let version_info_seq = 1001 + init.len() as u32;
+
+    // the first ClientVersionInfo never made it into the capture, but plenty came after it
+    let mut packets = vec![
+        tcp_packet(true, 1000, true, &[]),
+        tcp_packet(true, 1001, false, &init),
+    ];
+    for i in 1..=MAX_PENDING + 1 {
+        let seq = version_info_seq + (i * version_info.len()) as u32;
+        packets.push(tcp_packet(true, seq, false, &version_info));
+    }
+    let events = dissect(&pcapng(&packets), KEY_PHRASE);
+
+    let notes: Vec<_> = events
+        .iter()
+        .filter_map(|event| match event {
+            Event::Note { text, .. } => Some(text.as_str()),
+            Event::Segment(_) => None,
+        })
+        .collect();
+    assert_eq!(
+        notes,
+        [
+            format!("gap in stream, skipping {} bytes", version_info.len()),
+            "using the key for game version 6100".to_string(),
+        ]
+    );
+
+    // and everything after the gap still comes out
+    let version_infos = events
+        .iter()
+        .filter(|event| match event {
+            Event::Segment(segment) => {
+                segment.opcode_name().as_deref() == Some("ClientVersionInfo")
+            }
+            Event::Note { .. } => false,
+        })
+        .count();
+    assert_eq!(version_infos, MAX_PENDING + 1);
+}
@@ -0,0 +1,45 @@
+use pcap_import::stream::{Reassembler, MAX_PENDING};
+
+#[test]
+fn puts_segments_back_in_order() {
+    let mut reassembler = Reassembler::default();
+    assert!(reassembler.push(99, true, &[]).bytes.is_empty());
+
+    assert!(reassembler.push(103, false, b"def").bytes.is_empty());
+    assert_eq!(reassembler.push(100, false, b"abc").bytes, b"abcdef");
+    // retransmissions, whole or overlapping, only give what's new
+    assert!(reassembler.push(100, false, b"abc").bytes.is_empty());
+    assert_eq!(reassembler.push(104, false, b"efgh").bytes, b"gh");
+}
+
+#[test]
+fn sequence_numbers_wrap() {
+    let mut reassembler = Reassembler::default();
+    assert_eq!(reassembler.push(u32::MAX - 1, false, b"ab").bytes, b"ab");
+    assert!(reassembler.push(1, false, b"d").bytes.is_empty());
+    assert_eq!(reassembler.push(0, false, b"c").bytes, b"cd");
+}
+
+#[test]
+fn gives_up_on_segments_that_never_arrive() {
+    let mut reassembler = Reassembler::default();
+    assert_eq!(reassembler.push(0, false, b"ab").bytes, b"ab");
+
+    // 2..4 is lost, and everything after it waits until there's too much to wait for
+    for i in 0..MAX_PENDING as u32 {
+        let pushed = reassembler.push(4 + i, false, b"x");
+        assert!(pushed.skipped.is_none() && pushed.bytes.is_empty());
+    }
+    let pushed = reassembler.push(4 + MAX_PENDING as u32, false, b"y");
+    assert_eq!(pushed.skipped, Some(2));
+    let mut expected = vec![b'x'; MAX_PENDING];
+    expected.push(b'y');
+    assert_eq!(pushed.bytes, expected);
+
+    // and the stream carries on from there
+    assert!(reassembler.push(2, false, b"cd").bytes.is_empty());
+    assert_eq!(
+        reassembler.push(5 + MAX_PENDING as u32, false, b"z").bytes,
+        b"z"
+    );
+}
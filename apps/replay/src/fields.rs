@@ -0,0 +1,249 @@
+use lobby::ipc::{
+    IPCCharCreate, IPCCharList, IPCEnterWorld, IPCLobbyError, IPCRetainerList, IPCServerList,
+    IPCServiceIDInfo, MAX_CHARACTERS, MAX_RETAINERS, MAX_SERVERS, MAX_SERVICE_ACCOUNTS,
+};
+use sapphire_protocol::{
+    ipc::{read_c_string, Direction, IpcPacket},
+    opcodes::OpcodeTable,
+    packets::SegmentType,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Kind {
+    // little endian, eight bytes at most
+    Int,
+    // null terminated inside its buffer
+    Str,
+    Bytes,
+}
+
+#[derive(Debug)]
+pub struct Field {
+    pub name: String,
+    pub offset: usize,
+    pub len: usize,
+    pub kind: Kind,
+    // different on every run, so never compared
+    pub masked: bool,
+}
+
+// lays fields out one after the other, the way binrw writes the lobby's structs
+#[derive(Default)]
+struct Layout {
+    fields: Vec<Field>,
+    offset: usize,
+    prefix: String,
+}
+
+impl Layout {
+    fn field(&mut self, name: &str, len: usize, kind: Kind, masked: bool) -> &mut Layout {
+        self.fields.push(Field {
+            name: format!("{}{}", self.prefix, name),
+            offset: self.offset,
+            len,
+            kind,
+            masked,
+        });
+        self.offset += len;
+        self
+    }
+
+    fn int(&mut self, name: &str, len: usize) -> &mut Layout {
+        self.field(name, len, Kind::Int, false)
+    }
+
+    fn string(&mut self, name: &str, len: usize) -> &mut Layout {
+        self.field(name, len, Kind::Str, false)
+    }
+
+    fn bytes(&mut self, name: &str, len: usize) -> &mut Layout {
+        self.field(name, len, Kind::Bytes, false)
+    }
+
+    fn masked(&mut self, name: &str, len: usize, kind: Kind) -> &mut Layout {
+        self.field(name, len, kind, true)
+    }
+
+    fn repeat(&mut self, name: &str, count: usize, entry: fn(&mut Layout)) -> &mut Layout {
+        for i in 0..count {
+            self.prefix = format!("{}[{}].", name, i);
+            entry(self);
+        }
+        self.prefix.clear();
+        self
+    }
+}
+
+// the fields of a segment as the lobby sends it, offsets from the start of the segment data.
+// IPCs are told apart by the opcodes of the game version the client used, without them only
+// the header gets fields
+pub fn segment_fields(segment_type: u16, data: &[u8], opcodes: Option<&OpcodeTable>) -> Vec<Field> {
+    let mut layout = Layout::default();
+
+    if segment_type == SegmentType::Ipc as u16 && data.len() >= 4 {
+        let opcode = u16::from_le_bytes([data[2], data[3]]);
+        let name = opcodes.and_then(|opcodes| opcodes.name(Direction::ServerToClient, opcode));
+        ipc_fields(&mut layout, name);
+    } else if segment_type == SegmentType::KeepAlive as u16
+        || segment_type == SegmentType::KeepAliveResponse as u16
+    {
+        layout.int("id", 4).int("timestamp", 4);
+    }
+
+    layout.fields
+}
+
+fn ipc_fields(layout: &mut Layout, name: Option<&str>) {
+    layout
+        .int("reserved", 2)
+        .int("opcode", 2)
+        .int("padding", 2)
+        .int("server_id", 2)
+        .masked("timestamp", 4, Kind::Int)
+        .int("padding1", 4);
+
+    match name {
+        Some(IPCLobbyError::NAME) => {
+            layout
+                .int("seq", 8)
+                .int("error_id", 4)
+                .int("param", 4)
+                .int("message_id", 2)
+                .string("message", 516);
+        }
+        Some(IPCServiceIDInfo::NAME) => {
+            layout
+                .int("seq", 8)
+                .int("counter", 1)
+                .int("service_accounts_len", 1)
+                .int("u1", 1)
+                .int("u2", 1)
+                .int("padding1", 1)
+                .repeat("service_accounts", MAX_SERVICE_ACCOUNTS, |l| {
+                    l.int("id", 4)
+                        .int("unknown", 4)
+                        .int("index", 4)
+                        .string("name", 0x44);
+                });
+        }
+        Some(IPCServerList::NAME) => {
+            layout
+                .int("seq", 8)
+                .int("last", 2)
+                .int("offset", 2)
+                .int("servers_len", 4)
+                .int("padding", 4)
+                .int("padding1", 4)
+                .repeat("servers", MAX_SERVERS, |l| {
+                    l.int("id", 2)
+                        .int("index", 2)
+                        .int("flags", 4)
+                        .int("padding", 4)
+                        .int("icon", 4)
+                        .int("padding1", 4)
+                        .string("name", 0x40);
+                });
+        }
+        Some(IPCCharList::NAME) => {
+            layout
+                .int("seq", 8)
+                .int("counter", 1)
+                .int("characters_len", 1)
+                .int("padding", 2)
+                .bytes("unknown", 48)
+                .int("veteran_rank", 4)
+                .int("unknown1", 4)
+                .int("days_subscribed", 4)
+                .int("remaining_days", 4)
+                .int("days_to_next_rank", 4)
+                .int("max_characters_on_world", 2)
+                .int("unknown2", 2)
+                .int("entitled_expansion", 4)
+                .int("padding1", 4)
+                .repeat("characters", MAX_CHARACTERS, |l| {
+                    l.int("id", 4)
+                        .int("padding", 4)
+                        .int("content_id", 8)
+                        .int("index", 4)
+                        .int("padding1", 4)
+                        .int("world_id", 2)
+                        .int("current_world_id", 2)
+                        .bytes("unknown", 9)
+                        .string("name", 0x20)
+                        .string("world_name", 0x20)
+                        .string("current_world_name", 0x20)
+                        .string("detail_json", 1050);
+                });
+        }
+        Some(IPCCharCreate::NAME) => {
+            layout
+                .int("seq", 8)
+                .int("unknown", 1)
+                .int("unknown1", 1)
+                .int("create_type", 1)
+                .int("padding", 1)
+                .bytes("unknown2", 12)
+                .int("content_id", 8)
+                .bytes("unknown3", 12)
+                .string("name", 0x20)
+                .string("world_name", 0x20)
+                .string("current_world_name", 0x20);
+        }
+        Some(IPCEnterWorld::NAME) => {
+            layout
+                .int("seq", 8)
+                .int("character_id", 4)
+                .int("padding", 4)
+                .int("content_id", 8)
+                .int("padding1", 4)
+                .masked("session_id", 66, Kind::Str)
+                .int("port", 2)
+                .string("host", 48)
+                .int("padding2", 8)
+                .int("padding3", 8);
+        }
+        Some(IPCRetainerList::NAME) => {
+            layout
+                .int("seq", 8)
+                .int("counter", 1)
+                .int("retainers_len", 1)
+                .int("padding", 2)
+                .int("padding1", 4)
+                .repeat("retainers", MAX_RETAINERS, |l| {
+                    l.int("id", 8)
+                        .int("owner_content_id", 8)
+                        .int("class_job", 1)
+                        .int("level", 1)
+                        .int("padding", 2)
+                        .int("padding1", 4)
+                        .string("name", 0x20);
+                });
+        }
+        // anything else gets compared byte for byte after the header
+        _ => (),
+    }
+}
+
+pub fn format_value(kind: Kind, bytes: &[u8]) -> String {
+    match kind {
+        Kind::Int if bytes.len() <= 8 => {
+            let mut value = [0; 8];
+            value[0..bytes.len()].copy_from_slice(bytes);
+            let value = u64::from_le_bytes(value);
+            format!("{} ({:#x})", value, value)
+        }
+        Kind::Str => format!("{:?}", read_c_string(bytes)),
+        _ => {
+            let hex: Vec<String> = bytes
+                .iter()
+                .take(32)
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            if bytes.len() > 32 {
+                format!("{} ..", hex.join(" "))
+            } else {
+                hex.join(" ")
+            }
+        }
+    }
+}
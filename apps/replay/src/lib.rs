@@ -0,0 +1,297 @@
+pub mod fields;
+
+use fake_client::{FakeClient, Segment};
+use fields::{format_value, segment_fields};
+use lobby::{
+    capture::CaptureRecord, client::Client, config::Config, connections::Connections,
+    worlds::WorldRegistry,
+};
+use sapphire_protocol::{
+    encryption::{decrypt_ipc, derive_key, encryption_init_key},
+    ipc::Direction,
+    opcodes::OpcodeTable,
+    packets::{parse_frame, SegmentType},
+};
+use std::{error::Error, fmt, sync::Arc, time::Duration};
+use storage::Storage;
+use tokio::{
+    io::{duplex, DuplexStream},
+    time,
+};
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+// how long to wait for each response before calling it missing
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(2);
+// how long to wait for anything the lobby sends after the last recorded response
+const TRAILING_TIMEOUT: Duration = Duration::from_millis(200);
+
+// one frame from the client and everything the lobby sent back before the next one
+pub struct Exchange {
+    pub frame: Vec<u8>,
+    pub segments: Vec<CaptureRecord>,
+    pub responses: Vec<CaptureRecord>,
+}
+
+// server keepalives go out on a timer, so whether they land in the capture is down to timing
+fn is_server_keepalive(segment_type: u16) -> bool {
+    segment_type == SegmentType::KeepAlive as u16
+}
+
+pub fn exchanges(records: &[CaptureRecord]) -> Vec<Exchange> {
+    let mut exchanges: Vec<Exchange> = Vec::new();
+
+    for record in records {
+        match (record.is_from_client(), record.is_frame()) {
+            (true, true) => exchanges.push(Exchange {
+                frame: record.data.clone(),
+                segments: Vec::new(),
+                responses: Vec::new(),
+            }),
+            (true, false) => {
+                if let Some(exchange) = exchanges.last_mut() {
+                    exchange.segments.push(record.clone());
+                }
+            }
+            (false, false) if !is_server_keepalive(record.segment_type) => {
+                if let Some(exchange) = exchanges.last_mut() {
+                    exchange.responses.push(record.clone());
+                }
+            }
+            // the server's frames are just its segments encrypted
+            _ => (),
+        }
+    }
+
+    exchanges
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Difference {
+    // which client frame this answered, and which of the answers it was
+    pub exchange: usize,
+    pub response: usize,
+    pub field: String,
+    pub recorded: String,
+    pub replayed: String,
+}
+
+impl fmt::Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "frame {} response {} {}: recorded {}, replayed {}",
+            self.exchange, self.response, self.field, self.recorded, self.replayed
+        )
+    }
+}
+
+// the key the recorded client used and the game version it came from, worked out the way the
+// lobby does: try every configured game version until one turns the frame as sent into the
+// segment as the lobby decrypted it
+fn recover_key(
+    init: &CaptureRecord,
+    exchange: &Exchange,
+    config: &Config,
+) -> Result<(Vec<u8>, u16)> {
+    let (key, key_phrase) =
+        encryption_init_key(&init.data).ok_or("EncryptionInit too short for its key")?;
+    let candidates: Vec<_> = config
+        .client_versions
+        .iter()
+        .map(|version| {
+            (
+                derive_key(key, key_phrase, version.game_version),
+                version.game_version,
+            )
+        })
+        .collect();
+
+    let (_, raw) = parse_frame(&exchange.frame, &config.frame_limits())?;
+    for record in &exchange.segments {
+        if record.segment_type != SegmentType::Ipc as u16 {
+            continue;
+        }
+        let sent = match raw.get(record.segment_index as usize) {
+            Some(segment) => segment,
+            None => continue,
+        };
+
+        if let Some(candidate) = candidates
+            .iter()
+            .find(|(key, _)| decrypt_ipc(key, Direction::ClientToServer, &sent.data) == record.data)
+        {
+            return Ok(candidate.clone());
+        }
+    }
+
+    Err("none of the configured client versions decrypt the recorded IPC".into())
+}
+
+fn slice(data: &[u8], offset: usize, len: usize) -> &[u8] {
+    let start = offset.min(data.len());
+    let end = (offset + len).min(data.len());
+    &data[start..end]
+}
+
+fn compare(
+    exchange: usize,
+    response: usize,
+    recorded: &CaptureRecord,
+    replayed: &Segment,
+    opcodes: Option<&OpcodeTable>,
+    differences: &mut Vec<Difference>,
+) {
+    let mut differ = |field: String, recorded: String, replayed: String| {
+        differences.push(Difference {
+            exchange,
+            response,
+            field,
+            recorded,
+            replayed,
+        })
+    };
+
+    if recorded.segment_type != replayed.header.segment_type {
+        differ(
+            "segment type".to_string(),
+            recorded.segment_type.to_string(),
+            replayed.header.segment_type.to_string(),
+        );
+        return;
+    }
+    if recorded.data.len() != replayed.data.len() {
+        differ(
+            "length".to_string(),
+            recorded.data.len().to_string(),
+            replayed.data.len().to_string(),
+        );
+    }
+
+    let fields = segment_fields(recorded.segment_type, &recorded.data, opcodes);
+    for field in &fields {
+        let ours = slice(&recorded.data, field.offset, field.len);
+        let theirs = slice(&replayed.data, field.offset, field.len);
+        if !field.masked && ours != theirs {
+            differ(
+                field.name.clone(),
+                format_value(field.kind, ours),
+                format_value(field.kind, theirs),
+            );
+        }
+    }
+
+    // whatever no field covers still has to match byte for byte
+    let covered = fields.last().map_or(0, |f| f.offset + f.len);
+    let len = recorded.data.len().max(replayed.data.len());
+    let ours = slice(&recorded.data, covered, len);
+    let theirs = slice(&replayed.data, covered, len);
+    if ours != theirs {
+        differ(
+            format!("bytes {}..{}", covered, len),
+            format_value(fields::Kind::Bytes, ours),
+            format_value(fields::Kind::Bytes, theirs),
+        );
+    }
+}
+
+async fn next_response(
+    client: &mut FakeClient<DuplexStream>,
+    key: Option<&[u8]>,
+) -> Result<Segment> {
+    loop {
+        let mut segment = client.recv_segment().await?;
+        if is_server_keepalive(segment.header.segment_type) {
+            continue;
+        }
+
+        if let (true, Some(key)) = (segment.header.segment_type == SegmentType::Ipc as u16, key) {
+            segment.data = decrypt_ipc(key, Direction::ServerToClient, &segment.data);
+        }
+        return Ok(segment);
+    }
+}
+
+// plays the client's frames into a fresh lobby exactly as they were recorded, so the lobby
+// derives the same key from the same EncryptionInit, then compares each response with the
+// recorded one
+pub async fn replay(
+    records: &[CaptureRecord],
+    db: Arc<dyn Storage>,
+    config: Arc<Config>,
+) -> Result<Vec<Difference>> {
+    let worlds = WorldRegistry::new(config.clone());
+    let (ours, theirs) = duplex(0x10000);
+    let mut lobby = Client::new(theirs, db, config.clone(), worlds, Connections::default());
+    tokio::spawn(async move { lobby.handle().await });
+
+    // the game version only matters to the key, which we set ourselves
+    let mut client = FakeClient::new(ours, 0);
+    let mut key: Option<Vec<u8>> = None;
+    let mut opcodes: Option<&OpcodeTable> = None;
+    let mut init: Option<&CaptureRecord> = None;
+    let mut differences = Vec::new();
+
+    let exchanges = exchanges(records);
+    for (i, exchange) in exchanges.iter().enumerate() {
+        if let Some(record) = exchange
+            .segments
+            .iter()
+            .find(|r| r.segment_type == SegmentType::EncryptionInit as u16)
+        {
+            init = Some(record);
+        }
+        if let (None, Some(init)) = (&key, init) {
+            if exchange
+                .segments
+                .iter()
+                .any(|r| r.segment_type == SegmentType::Ipc as u16)
+            {
+                let (recovered, game_version) = recover_key(init, exchange, &config)?;
+                key = Some(recovered);
+                opcodes = config.opcode_tables.get(game_version);
+            }
+        }
+
+        client.send_frame(&exchange.frame).await?;
+
+        for (j, recorded) in exchange.responses.iter().enumerate() {
+            match time::timeout(RESPONSE_TIMEOUT, next_response(&mut client, key.as_deref())).await
+            {
+                Ok(Ok(replayed)) => compare(i, j, recorded, &replayed, opcodes, &mut differences),
+                Ok(Err(e)) => {
+                    differences.push(Difference {
+                        exchange: i,
+                        response: j,
+                        field: "segment".to_string(),
+                        recorded: format!("type {}", recorded.segment_type),
+                        replayed: format!("connection closed ({})", e),
+                    });
+                    return Ok(differences);
+                }
+                Err(_) => differences.push(Difference {
+                    exchange: i,
+                    response: j,
+                    field: "segment".to_string(),
+                    recorded: format!("type {}", recorded.segment_type),
+                    replayed: "nothing".to_string(),
+                }),
+            }
+        }
+    }
+
+    // the lobby saying more than it did last time is a difference too
+    if let Ok(Ok(replayed)) =
+        time::timeout(TRAILING_TIMEOUT, next_response(&mut client, key.as_deref())).await
+    {
+        differences.push(Difference {
+            exchange: exchanges.len().saturating_sub(1),
+            response: exchanges.last().map_or(0, |e| e.responses.len()),
+            field: "segment".to_string(),
+            recorded: "nothing".to_string(),
+            replayed: format!("type {}", replayed.header.segment_type),
+        });
+    }
+
+    Ok(differences)
+}
@@ -0,0 +1,70 @@
+use lobby::{capture::read_capture, config::Config};
+use replay::replay;
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    sync::Arc,
+};
+use storage::{SqliteStorage, Storage};
+
+const USAGE: &str = "usage:
+    replay <capture> [database]";
+
+// the copy has every session id in it, so it goes however replay() ends
+struct TempCopy(PathBuf);
+
+impl TempCopy {
+    fn new(from: &str) -> Result<TempCopy, Box<dyn Error>> {
+        let copy = TempCopy(env::temp_dir().join(format!("replay-{}.db", process::id())));
+        fs::copy(from, copy.path())?;
+        Ok(copy)
+    }
+
+    fn path(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TempCopy {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.is_empty() || args.len() > 2 {
+        println!("{}", USAGE);
+        process::exit(2);
+    }
+
+    let config_path = env::var("LOBBY_CONFIG").unwrap_or_else(|_| "lobby.toml".to_string());
+    let mut config = Config::load(&config_path)?;
+    // replaying shouldn't leave captures of its own behind
+    config.capture_dir = None;
+
+    // deleting a character or joining a queue would change the real thing otherwise
+    let database = args.get(1).unwrap_or(&config.database);
+    let copy = TempCopy::new(database)?;
+
+    let records = read_capture(&args[0])?;
+    let db: Arc<dyn Storage> = Arc::new(SqliteStorage::open(copy.path())?);
+    let differences = replay(&records, db, Arc::new(config)).await?;
+    drop(copy);
+
+    for difference in &differences {
+        println!("{}", difference);
+    }
+
+    if differences.is_empty() {
+        println!("no differences");
+        Ok(())
+    } else {
+        println!("{} differences", differences.len());
+        process::exit(1);
+    }
+}
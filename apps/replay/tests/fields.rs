@@ -0,0 +1,55 @@
+use lobby::{
+    config::Config,
+    ipc::{
+        IPCCharCreate, IPCCharList, IPCEnterWorld, IPCLobbyError, IPCRetainerList, IPCServerList,
+        IPCServiceIDInfo,
+    },
+};
+use replay::fields::{segment_fields, Field};
+use sapphire_protocol::{
+    ipc::{IpcPacket, WireSize},
+    packets::SegmentType,
+};
+
+const IPC_HEADER_SIZE: usize = 0x10;
+
+fn fields_of<T: IpcPacket + WireSize>() -> Vec<Field> {
+    let config = Config::default();
+    let opcodes = config.opcode_tables.get(6100).unwrap();
+    let opcode = opcodes.opcode_of::<T>().unwrap();
+
+    let mut data = vec![0; IPC_HEADER_SIZE + T::SIZE];
+    data[2..4].copy_from_slice(&opcode.to_le_bytes());
+    segment_fields(SegmentType::Ipc as u16, &data, Some(opcodes))
+}
+
+// the layouts are written out by hand, so check them against what binrw really writes
+fn assert_covers<T: IpcPacket + WireSize>() {
+    let fields = fields_of::<T>();
+    let mut offset = 0;
+    for field in &fields {
+        assert_eq!(field.offset, offset, "{} {}", T::NAME, field.name);
+        offset += field.len;
+    }
+    assert_eq!(offset, IPC_HEADER_SIZE + T::SIZE, "{}", T::NAME);
+}
+
+#[test]
+fn layouts_match_the_structs() {
+    assert_covers::<IPCLobbyError>();
+    assert_covers::<IPCServiceIDInfo>();
+    assert_covers::<IPCServerList>();
+    assert_covers::<IPCCharList>();
+    assert_covers::<IPCCharCreate>();
+    assert_covers::<IPCEnterWorld>();
+    assert_covers::<IPCRetainerList>();
+}
+
+#[test]
+fn unknown_ipcs_only_get_their_header() {
+    let mut data = vec![0; 0x40];
+    data[2..4].copy_from_slice(&0xffff_u16.to_le_bytes());
+    let fields = segment_fields(SegmentType::Ipc as u16, &data, None);
+    let covered = fields.last().map_or(0, |f| f.offset + f.len);
+    assert_eq!(covered, IPC_HEADER_SIZE);
+}
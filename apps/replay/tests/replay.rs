@@ -0,0 +1,122 @@
+use fake_client::{FakeClient, CHAR_LIST, RETAINER_LIST, SERVER_LIST, SERVICE_ACCOUNT_LIST};
+use lobby::{
+    capture::{read_capture, CaptureRecord},
+    client::Client,
+    config::{Config, WorldConfig},
+    connections::Connections,
+    worlds::WorldRegistry,
+};
+use replay::{replay, Difference};
+use std::sync::Arc;
+use storage::{
+    AccountRepository, CharacterRepository, ServiceAccountRepository, SessionRepository,
+    SqliteStorage,
+};
+use tokio::io::duplex;
+
+const SESSION_ID: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789ab";
+
+// built the same way every time, so the ids line up between recording and replaying
+fn database(service_account: &str) -> Arc<SqliteStorage> {
+    let db = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let account_id = db.add_account("test").unwrap();
+    db.add_session(account_id, SESSION_ID).unwrap();
+    db.add_service_account(account_id, service_account).unwrap();
+    db.add_character(account_id, 1, "Test Character").unwrap();
+    db
+}
+
+fn config(capture_dir: Option<String>) -> Config {
+    Config {
+        worlds: vec![WorldConfig {
+            id: 1,
+            name: "Ultros".to_string(),
+            data_centre: "Sapphire".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 54992,
+            congested_at: 500,
+            capacity: 600,
+            closed_for_creation: false,
+        }],
+        capture_dir,
+        ..Config::default()
+    }
+}
+
+async fn record_session(name: &str) -> Vec<CaptureRecord> {
+    let dir = std::env::temp_dir().join(format!("replay-test-{}-{}", name, std::process::id()));
+    let config = Arc::new(config(Some(dir.to_string_lossy().into_owned())));
+    let worlds = WorldRegistry::new(config.clone());
+
+    let (ours, theirs) = duplex(0x10000);
+    let mut lobby = Client::new(
+        theirs,
+        database("FINAL FANTASY XIV"),
+        config,
+        worlds,
+        Connections::default(),
+    );
+    tokio::spawn(async move { lobby.handle().await });
+
+    let mut client = FakeClient::new(ours, 6100);
+    client
+        .encryption_init("test key phrase", 0xdeadbeef)
+        .await
+        .unwrap();
+    client
+        .client_version_info(1, SESSION_ID, "2022.05.19.0000.0000")
+        .await
+        .unwrap();
+    client.expect_ipc(SERVICE_ACCOUNT_LIST).await.unwrap();
+    client.req_char_list(2).await.unwrap();
+    client.expect_ipc(SERVER_LIST).await.unwrap();
+    client.expect_ipc(CHAR_LIST).await.unwrap();
+    client.expect_ipc(RETAINER_LIST).await.unwrap();
+
+    let path = std::fs::read_dir(&dir)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let records = read_capture(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+    records
+}
+
+#[tokio::test]
+async fn replaying_into_the_same_database_matches() {
+    let records = record_session("same").await;
+
+    let differences = replay(
+        &records,
+        database("FINAL FANTASY XIV"),
+        Arc::new(config(None)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(differences, []);
+}
+
+#[tokio::test]
+async fn changed_fields_are_reported_by_name() {
+    let records = record_session("changed").await;
+
+    let differences = replay(
+        &records,
+        database("FINAL FANTASY XIV 2"),
+        Arc::new(config(None)),
+    )
+    .await
+    .unwrap();
+    assert_eq!(
+        differences,
+        [Difference {
+            exchange: 1,
+            response: 0,
+            field: "service_accounts[0].name".to_string(),
+            recorded: "\"FINAL FANTASY XIV\"".to_string(),
+            replayed: "\"FINAL FANTASY XIV 2\"".to_string(),
+        }]
+    );
+}
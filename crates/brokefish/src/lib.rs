@@ -1,6 +1,11 @@
 mod consts;
 
 fn next_u32_wrap(buf: &[u8], offset: &mut usize) -> u32 {
+    // an empty key has nothing to wrap around to, so it mixes in as zeroes
+    if buf.is_empty() {
+        return 0;
+    }
+
     let mut v = 0;
 
     for _ in 0..4 {
@@ -124,10 +129,19 @@ impl Brokefish {
             data.len() + (8 - (data.len() % 8))
         };
 
+        // a trailing partial block is padded with zeroes, same as encrypt
+        let mut padded_data: Vec<u8> = vec![0; padded_length];
+        padded_data[0..data.len()].copy_from_slice(data);
+
         let mut buf: Vec<u8> = vec![0; padded_length];
         for i in (0..padded_length).step_by(8) {
-            let mut l = u32::from_le_bytes(data[i..i + 4].try_into().expect("couldn't get l"));
-            let mut r = u32::from_le_bytes(data[i + 4..i + 8].try_into().expect("couldn't get r"));
+            let mut l =
+                u32::from_le_bytes(padded_data[i..i + 4].try_into().expect("couldn't get l"));
+            let mut r = u32::from_le_bytes(
+                padded_data[i + 4..i + 8]
+                    .try_into()
+                    .expect("couldn't get r"),
+            );
             [l, r] = self.decrypt_block([l, r]);
 
             buf[i..i + 4].copy_from_slice(&l.to_le_bytes());
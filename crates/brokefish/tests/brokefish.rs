@@ -0,0 +1,24 @@
+use brokefish::Brokefish;
+
+#[test]
+fn empty_key() {
+    let bf = Brokefish::new(&[]);
+    let data = [1, 2, 3, 4, 5, 6, 7, 8];
+    assert_eq!(bf.decrypt(&bf.encrypt(&data)), data);
+}
+
+#[test]
+fn decrypting_a_partial_block() {
+    let bf = Brokefish::new(b"key");
+    assert_eq!(bf.decrypt(&[0; 3]).len(), 8);
+    assert_eq!(bf.decrypt(&[0; 9]).len(), 16);
+}
+
+#[test]
+fn round_trip() {
+    let bf = Brokefish::new(b"a rather longer key than that");
+    let data: Vec<u8> = (0..64).collect();
+    let encrypted = bf.encrypt(&data);
+    assert_ne!(encrypted, data);
+    assert_eq!(bf.decrypt(&encrypted), data);
+}
@@ -0,0 +1,187 @@
+use binrw::{BinRead, BinWrite};
+use serde::{
+    de::{self, Unexpected},
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+use std::{error::Error, fmt, io::Cursor};
+
+pub const CUSTOMIZE_SIZE: usize = 26;
+
+pub const RACE_HYUR: u8 = 1;
+pub const RACE_ELEZEN: u8 = 2;
+pub const RACE_LALAFELL: u8 = 3;
+pub const RACE_MIQOTE: u8 = 4;
+pub const RACE_ROEGADYN: u8 = 5;
+pub const RACE_AU_RA: u8 = 6;
+pub const RACE_HROTHGAR: u8 = 7;
+pub const RACE_VIERA: u8 = 8;
+
+pub const GENDER_MALE: u8 = 0;
+pub const GENDER_FEMALE: u8 = 1;
+
+// elderly and child bodies exist for npcs, players only ever get the adult one
+pub const BODY_TYPE_ADULT: u8 = 1;
+
+// set on top of the option picked in a few fields
+pub const HIGHLIGHTS_ON: u8 = 0x80;
+pub const FACE_PAINT_FLIPPED: u8 = 0x80;
+pub const LIP_COLOUR_LIGHT: u8 = 0x80;
+
+// the 26 bytes the client sends on creation and gets back in the character list, in order
+#[derive(BinRead, BinWrite, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Customize {
+    pub race: u8,
+    pub gender: u8,
+    pub body_type: u8,
+    // 0-100, the slider in the character creator
+    pub height: u8,
+    // each race has two, numbered race * 2 - 1 and race * 2
+    pub tribe: u8,
+    pub face: u8,
+    pub hair_style: u8,
+    pub highlights: u8,
+    pub skin_colour: u8,
+    pub eye_colour: u8,
+    pub hair_colour: u8,
+    pub highlights_colour: u8,
+    // a bitfield of the face's optional features (tattoos, moles...)
+    pub facial_features: u8,
+    pub facial_features_colour: u8,
+    pub eyebrows: u8,
+    // the second eye, for heterochromia
+    pub eye_colour2: u8,
+    pub eye_shape: u8,
+    pub nose: u8,
+    pub jaw: u8,
+    pub mouth: u8,
+    pub lip_colour: u8,
+    // muscle tone, ear length or tail length depending on the race, 0-100
+    pub race_feature_size: u8,
+    pub race_feature_type: u8,
+    // 0-100
+    pub bust: u8,
+    pub face_paint: u8,
+    pub face_paint_colour: u8,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum CustomizeError {
+    OutOfRange { field: &'static str, value: u8 },
+    // the tribe belongs to another race
+    WrongTribe { race: u8, tribe: u8 },
+    // hrothgar can only be male and viera only female
+    GenderUnavailable { race: u8, gender: u8 },
+}
+
+impl fmt::Display for CustomizeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CustomizeError::OutOfRange { field, value } => {
+                write!(f, "{} can't be {}", field, value)
+            }
+            CustomizeError::WrongTribe { race, tribe } => {
+                write!(f, "tribe {} isn't one of race {}'s", tribe, race)
+            }
+            CustomizeError::GenderUnavailable { race, gender } => {
+                write!(f, "race {} can't have gender {}", race, gender)
+            }
+        }
+    }
+}
+
+impl Error for CustomizeError {}
+
+fn check_range(
+    field: &'static str,
+    value: u8,
+    range: std::ops::RangeInclusive<u8>,
+) -> Result<(), CustomizeError> {
+    if range.contains(&value) {
+        Ok(())
+    } else {
+        Err(CustomizeError::OutOfRange { field, value })
+    }
+}
+
+impl Customize {
+    pub fn from_bytes(bytes: &[u8; CUSTOMIZE_SIZE]) -> Customize {
+        Customize::read(&mut Cursor::new(&bytes[..])).expect("customize is exactly 26 bytes")
+    }
+
+    pub fn to_bytes(&self) -> [u8; CUSTOMIZE_SIZE] {
+        let mut bytes = [0; CUSTOMIZE_SIZE];
+        self.write_to(&mut Cursor::new(&mut bytes[..]))
+            .expect("customize is exactly 26 bytes");
+        bytes
+    }
+
+    // whether a player could have made this in the character creator. the ranges for things
+    // like faces and hair styles differ per tribe and patch, so those are left to the client
+    pub fn validate(&self) -> Result<(), CustomizeError> {
+        check_range("race", self.race, RACE_HYUR..=RACE_VIERA)?;
+        check_range("gender", self.gender, GENDER_MALE..=GENDER_FEMALE)?;
+        check_range(
+            "body type",
+            self.body_type,
+            BODY_TYPE_ADULT..=BODY_TYPE_ADULT,
+        )?;
+        check_range("height", self.height, 0..=100)?;
+
+        if self.tribe != self.race * 2 - 1 && self.tribe != self.race * 2 {
+            return Err(CustomizeError::WrongTribe {
+                race: self.race,
+                tribe: self.tribe,
+            });
+        }
+
+        let gender_available = match self.race {
+            RACE_HROTHGAR => self.gender == GENDER_MALE,
+            RACE_VIERA => self.gender == GENDER_FEMALE,
+            _ => true,
+        };
+        if !gender_available {
+            return Err(CustomizeError::GenderUnavailable {
+                race: self.race,
+                gender: self.gender,
+            });
+        }
+
+        check_range("face", self.face, 1..=u8::MAX)?;
+        check_range("hair style", self.hair_style, 1..=u8::MAX)?;
+        if self.highlights != 0 && self.highlights != HIGHLIGHTS_ON {
+            return Err(CustomizeError::OutOfRange {
+                field: "highlights",
+                value: self.highlights,
+            });
+        }
+        check_range("race feature size", self.race_feature_size, 0..=100)?;
+        check_range("bust", self.bust, 0..=100)?;
+
+        Ok(())
+    }
+}
+
+// the client's JSON description of a character has the look as the same 26 bytes, in order, each
+// a string of its decimal value: ["1","0","1","50",...]
+impl Serialize for Customize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.to_bytes().iter().map(|b| b.to_string()))
+    }
+}
+
+impl<'de> Deserialize<'de> for Customize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let values = Vec::<String>::deserialize(deserializer)?;
+        if values.len() != CUSTOMIZE_SIZE {
+            return Err(de::Error::invalid_length(values.len(), &"26 values"));
+        }
+
+        let mut bytes = [0; CUSTOMIZE_SIZE];
+        for (byte, value) in bytes.iter_mut().zip(&values) {
+            *byte = value.parse().map_err(|_| {
+                de::Error::invalid_value(Unexpected::Str(value), &"a number from 0 to 255")
+            })?;
+        }
+        Ok(Customize::from_bytes(&bytes))
+    }
+}
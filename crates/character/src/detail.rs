@@ -0,0 +1,54 @@
+use crate::{Customize, CustomizeError};
+use serde::Deserialize;
+use serde_json::Value;
+use std::{error::Error, fmt};
+
+// the client's JSON description of a character, the way the character list carries it:
+// {"content":[name, [class levels], ..., [customize], ...],"classname":"ClientSelectData",...}
+// the appearance is the only part we can check, the rest is the client's business
+pub const DETAIL_CUSTOMIZE_INDEX: usize = 12;
+
+#[derive(Deserialize)]
+struct Detail {
+    content: Vec<Value>,
+}
+
+#[derive(Debug)]
+pub enum DetailError {
+    // not JSON, or no content array
+    Json(serde_json::Error),
+    // content stops before the appearance
+    NoCustomize,
+    // the appearance isn't 26 numbers
+    BadCustomize(serde_json::Error),
+    Invalid(CustomizeError),
+}
+
+impl fmt::Display for DetailError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DetailError::Json(e) => write!(f, "not a character description: {}", e),
+            DetailError::NoCustomize => write!(
+                f,
+                "the description has no appearance at content[{}]",
+                DETAIL_CUSTOMIZE_INDEX
+            ),
+            DetailError::BadCustomize(e) => write!(f, "the appearance can't be read: {}", e),
+            DetailError::Invalid(e) => write!(f, "impossible appearance: {}", e),
+        }
+    }
+}
+
+impl Error for DetailError {}
+
+// the appearance in a character description, if it's one a player could have made
+pub fn detail_customize(json: &str) -> Result<Customize, DetailError> {
+    let detail: Detail = serde_json::from_str(json).map_err(DetailError::Json)?;
+    let look = detail
+        .content
+        .get(DETAIL_CUSTOMIZE_INDEX)
+        .ok_or(DetailError::NoCustomize)?;
+    let customize = Customize::deserialize(look).map_err(DetailError::BadCustomize)?;
+    customize.validate().map_err(DetailError::Invalid)?;
+    Ok(customize)
+}
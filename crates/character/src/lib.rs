@@ -0,0 +1,7 @@
+mod customize;
+mod detail;
+mod name;
+
+pub use customize::*;
+pub use detail::*;
+pub use name::*;
@@ -0,0 +1,134 @@
+use std::{error::Error, fmt};
+
+pub const MIN_NAME_PART_LENGTH: usize = 2;
+pub const MAX_NAME_PART_LENGTH: usize = 15;
+// forename and surname together, not counting the space
+pub const MAX_NAME_LENGTH: usize = 20;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NameError {
+    // not a forename and a surname separated by one space
+    Parts,
+    Length,
+    Characters,
+    Capitalisation,
+    // a forename or surname can have one apostrophe and one hyphen, which can't start or end
+    // it or sit next to each other
+    Punctuation,
+    BannedWord,
+    Reserved,
+    // someone on the same world already has it. the rules can't know this, it's up to the
+    // caller to ask storage
+    Taken,
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            NameError::Parts => "a name is a forename and a surname",
+            NameError::Length => "a forename or surname is too short or too long",
+            NameError::Characters => "names can only have letters, apostrophes and hyphens",
+            NameError::Capitalisation => "names start with a capital and continue in lowercase",
+            NameError::Punctuation => "apostrophes or hyphens are repeated or misplaced",
+            NameError::BannedWord => "the name contains a banned word",
+            NameError::Reserved => "the name is reserved",
+            NameError::Taken => "the name is already taken on this world",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+impl Error for NameError {}
+
+fn is_punctuation(c: char) -> bool {
+    c == '\'' || c == '-'
+}
+
+fn check_part(part: &str) -> Result<(), NameError> {
+    let chars: Vec<char> = part.chars().collect();
+
+    if chars.len() < MIN_NAME_PART_LENGTH || chars.len() > MAX_NAME_PART_LENGTH {
+        return Err(NameError::Length);
+    }
+
+    if !chars
+        .iter()
+        .all(|&c| c.is_ascii_alphabetic() || is_punctuation(c))
+    {
+        return Err(NameError::Characters);
+    }
+
+    for mark in ['\'', '-'] {
+        if chars.iter().filter(|&&c| c == mark).count() > 1 {
+            return Err(NameError::Punctuation);
+        }
+    }
+    if is_punctuation(chars[0]) || is_punctuation(chars[chars.len() - 1]) {
+        return Err(NameError::Punctuation);
+    }
+    if chars
+        .windows(2)
+        .any(|pair| is_punctuation(pair[0]) && is_punctuation(pair[1]))
+    {
+        return Err(NameError::Punctuation);
+    }
+
+    // a capital is allowed after punctuation too, for names like Ka'Moko or Jean-Luc
+    for (i, &c) in chars.iter().enumerate() {
+        let capital_allowed = i == 0 || is_punctuation(chars[i - 1]);
+        if (i == 0 && !c.is_ascii_uppercase()) || (!capital_allowed && c.is_ascii_uppercase()) {
+            return Err(NameError::Capitalisation);
+        }
+    }
+
+    Ok(())
+}
+
+// the name rules a server enforces on top of the game's own, loaded from its config
+#[derive(Clone, Debug, Default)]
+pub struct NameRules {
+    // matched anywhere in the name, ignoring case
+    banned_words: Vec<String>,
+    // whole names nobody can take, ignoring case
+    reserved_names: Vec<String>,
+}
+
+impl NameRules {
+    pub fn new(banned_words: &[String], reserved_names: &[String]) -> NameRules {
+        NameRules {
+            banned_words: banned_words.iter().map(|w| w.to_lowercase()).collect(),
+            reserved_names: reserved_names.iter().map(|n| n.to_lowercase()).collect(),
+        }
+    }
+
+    // everything but uniqueness, which needs the world's characters
+    pub fn validate(&self, name: &str) -> Result<(), NameError> {
+        let parts: Vec<&str> = name.split(' ').collect();
+        let (forename, surname) = match parts[..] {
+            [forename, surname] => (forename, surname),
+            _ => return Err(NameError::Parts),
+        };
+
+        check_part(forename)?;
+        check_part(surname)?;
+        if forename.len() + surname.len() > MAX_NAME_LENGTH {
+            return Err(NameError::Length);
+        }
+
+        let lowercase = name.to_lowercase();
+        if self.reserved_names.contains(&lowercase) {
+            return Err(NameError::Reserved);
+        }
+        // the space is dropped so a word can't be snuck in across it
+        let joined = lowercase.replace(' ', "");
+        if self
+            .banned_words
+            .iter()
+            .any(|w| lowercase.contains(w.as_str()) || joined.contains(w.as_str()))
+        {
+            return Err(NameError::BannedWord);
+        }
+
+        Ok(())
+    }
+}
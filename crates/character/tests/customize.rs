@@ -0,0 +1,130 @@
+use character::{
+    Customize, CustomizeError, CUSTOMIZE_SIZE, HIGHLIGHTS_ON, RACE_HROTHGAR, RACE_VIERA,
+};
+use serde_json::json;
+
+const MIDLANDER: [u8; CUSTOMIZE_SIZE] = [
+    1, 0, 1, 50, 1, 1, 3, 0, 4, 5, 6, 7, 0, 8, 1, 5, 2, 3, 1, 2, 0x81, 50, 1, 50, 0, 0,
+];
+
+fn midlander() -> Customize {
+    Customize::from_bytes(&MIDLANDER)
+}
+
+#[test]
+fn packed_form_round_trips() {
+    let customize = midlander();
+    assert_eq!(customize.race, 1);
+    assert_eq!(customize.height, 50);
+    assert_eq!(customize.hair_style, 3);
+    assert_eq!(customize.lip_colour, 0x81);
+    assert_eq!(customize.bust, 50);
+    assert_eq!(customize.to_bytes(), MIDLANDER);
+
+    let bytes: [u8; CUSTOMIZE_SIZE] = std::array::from_fn(|i| i as u8);
+    assert_eq!(Customize::from_bytes(&bytes).to_bytes(), bytes);
+}
+
+#[test]
+fn json_form_is_the_clients() {
+    let expected: Vec<String> = MIDLANDER.iter().map(|b| b.to_string()).collect();
+    assert_eq!(serde_json::to_value(midlander()).unwrap(), json!(expected));
+
+    let read: Customize = serde_json::from_value(json!(expected)).unwrap();
+    assert_eq!(read, midlander());
+}
+
+#[test]
+fn json_form_needs_26_numbers() {
+    let mut values: Vec<String> = MIDLANDER.iter().map(|b| b.to_string()).collect();
+    values.pop();
+    assert!(serde_json::from_value::<Customize>(json!(values)).is_err());
+
+    values.push("256".to_string());
+    assert!(serde_json::from_value::<Customize>(json!(values)).is_err());
+
+    values.pop();
+    values.push("tall".to_string());
+    assert!(serde_json::from_value::<Customize>(json!(values)).is_err());
+
+    assert!(serde_json::from_value::<Customize>(json!(MIDLANDER)).is_err());
+}
+
+#[test]
+fn valid_appearances_pass() {
+    assert_eq!(midlander().validate(), Ok(()));
+
+    let mut customize = midlander();
+    customize.highlights = HIGHLIGHTS_ON;
+    customize.height = 0;
+    customize.bust = 100;
+    assert_eq!(customize.validate(), Ok(()));
+
+    let mut hrothgar = midlander();
+    hrothgar.race = RACE_HROTHGAR;
+    hrothgar.tribe = 14;
+    assert_eq!(hrothgar.validate(), Ok(()));
+}
+
+fn out_of_range(field: &'static str, value: u8) -> Result<(), CustomizeError> {
+    Err(CustomizeError::OutOfRange { field, value })
+}
+
+#[test]
+fn bad_fields_are_refused() {
+    let check = |change: fn(&mut Customize)| {
+        let mut customize = midlander();
+        change(&mut customize);
+        customize.validate()
+    };
+
+    assert_eq!(check(|c| c.race = 0), out_of_range("race", 0));
+    assert_eq!(check(|c| c.race = 9), out_of_range("race", 9));
+    assert_eq!(check(|c| c.gender = 2), out_of_range("gender", 2));
+    assert_eq!(check(|c| c.body_type = 0), out_of_range("body type", 0));
+    assert_eq!(check(|c| c.body_type = 2), out_of_range("body type", 2));
+    assert_eq!(check(|c| c.height = 101), out_of_range("height", 101));
+    assert_eq!(check(|c| c.face = 0), out_of_range("face", 0));
+    assert_eq!(check(|c| c.hair_style = 0), out_of_range("hair style", 0));
+    assert_eq!(check(|c| c.highlights = 1), out_of_range("highlights", 1));
+    assert_eq!(
+        check(|c| c.race_feature_size = 101),
+        out_of_range("race feature size", 101)
+    );
+    assert_eq!(check(|c| c.bust = 101), out_of_range("bust", 101));
+}
+
+#[test]
+fn tribes_and_genders_follow_the_race() {
+    let mut customize = midlander();
+    customize.tribe = 3;
+    assert_eq!(
+        customize.validate(),
+        Err(CustomizeError::WrongTribe { race: 1, tribe: 3 })
+    );
+
+    let mut hrothgar = midlander();
+    hrothgar.race = RACE_HROTHGAR;
+    hrothgar.tribe = 13;
+    hrothgar.gender = 1;
+    assert_eq!(
+        hrothgar.validate(),
+        Err(CustomizeError::GenderUnavailable {
+            race: RACE_HROTHGAR,
+            gender: 1
+        })
+    );
+
+    let mut viera = midlander();
+    viera.race = RACE_VIERA;
+    viera.tribe = 15;
+    assert_eq!(
+        viera.validate(),
+        Err(CustomizeError::GenderUnavailable {
+            race: RACE_VIERA,
+            gender: 0
+        })
+    );
+    viera.gender = 1;
+    assert_eq!(viera.validate(), Ok(()));
+}
@@ -0,0 +1,64 @@
+use character::{detail_customize, CustomizeError, DetailError, CUSTOMIZE_SIZE};
+use serde_json::{json, Value};
+
+const MIDLANDER: [u8; CUSTOMIZE_SIZE] = [
+    1, 0, 1, 50, 1, 1, 3, 0, 4, 5, 6, 7, 0, 8, 1, 5, 2, 3, 1, 2, 0x81, 50, 1, 50, 0, 0,
+];
+
+// laid out the way the character list has it
+fn detail(look: Value) -> String {
+    json!({
+        "content": [
+            "Test Character",
+            ["0", "1", "0", "0"],
+            "0", "0", "0", "1", "1", "1", "1", "0", "132", "0",
+            look,
+            "0", "0",
+            ["0", "0", "0", "0", "0", "0", "0", "0", "0", "0"],
+            "1", "0", "0", "0", "0", "0", "", "0", "0"
+        ],
+        "classname": "ClientSelectData",
+        "classid": 116
+    })
+    .to_string()
+}
+
+fn look(bytes: &[u8]) -> Value {
+    json!(bytes.iter().map(|b| b.to_string()).collect::<Vec<_>>())
+}
+
+#[test]
+fn reads_the_appearance() {
+    let customize = detail_customize(&detail(look(&MIDLANDER))).unwrap();
+    assert_eq!(customize.to_bytes(), MIDLANDER);
+}
+
+#[test]
+fn impossible_appearances_are_refused() {
+    let mut viera = MIDLANDER;
+    viera[0] = 8;
+    viera[4] = 15;
+    assert!(matches!(
+        detail_customize(&detail(look(&viera))),
+        Err(DetailError::Invalid(CustomizeError::GenderUnavailable {
+            race: 8,
+            gender: 0
+        }))
+    ));
+}
+
+#[test]
+fn anything_else_is_refused() {
+    assert!(matches!(
+        detail_customize("not json"),
+        Err(DetailError::Json(_))
+    ));
+    assert!(matches!(
+        detail_customize(r#"{"content":["Test Character"]}"#),
+        Err(DetailError::NoCustomize)
+    ));
+    assert!(matches!(
+        detail_customize(&detail(look(&MIDLANDER[1..]))),
+        Err(DetailError::BadCustomize(_))
+    ));
+}
@@ -0,0 +1,116 @@
+use character::{NameError, NameRules};
+
+fn rules() -> NameRules {
+    NameRules::new(
+        &["Bad".to_string(), "worse".to_string()],
+        &["Reserved Name".to_string()],
+    )
+}
+
+#[test]
+fn ordinary_names_pass() {
+    let rules = rules();
+    assert_eq!(rules.validate("Test Character"), Ok(()));
+    assert_eq!(rules.validate("Ka'Moko Jean-Luc"), Ok(()));
+    assert_eq!(rules.validate("Ab Cd"), Ok(()));
+    assert_eq!(NameRules::default().validate("Badger Worsey"), Ok(()));
+}
+
+#[test]
+fn names_have_two_parts() {
+    let rules = rules();
+    assert_eq!(rules.validate("Mononym"), Err(NameError::Parts));
+    assert_eq!(rules.validate("Too Many Parts"), Err(NameError::Parts));
+    assert_eq!(rules.validate("Two  Spaces"), Err(NameError::Parts));
+    assert_eq!(rules.validate(" Leading Space"), Err(NameError::Parts));
+}
+
+#[test]
+fn lengths() {
+    let rules = rules();
+    assert_eq!(rules.validate("A Character"), Err(NameError::Length));
+    assert_eq!(rules.validate("Character B"), Err(NameError::Length));
+    // 15 letters is the most for either part
+    assert_eq!(rules.validate("Abcdefghijklmno Ab"), Ok(()));
+    assert_eq!(
+        rules.validate("Abcdefghijklmnop Ab"),
+        Err(NameError::Length)
+    );
+    // and 20 for both together
+    assert_eq!(rules.validate("Abcdefghij Abcdefghij"), Ok(()));
+    assert_eq!(
+        rules.validate("Abcdefghijk Abcdefghij"),
+        Err(NameError::Length)
+    );
+}
+
+#[test]
+fn characters_and_punctuation() {
+    let rules = rules();
+    assert_eq!(rules.validate("Test Ch4racter"), Err(NameError::Characters));
+    assert_eq!(rules.validate("Tést Character"), Err(NameError::Characters));
+    assert_eq!(
+        rules.validate("'Test Character"),
+        Err(NameError::Punctuation)
+    );
+    assert_eq!(
+        rules.validate("Test Character-"),
+        Err(NameError::Punctuation)
+    );
+    assert_eq!(
+        rules.validate("Te'-st Character"),
+        Err(NameError::Punctuation)
+    );
+    // one of each per part, however far apart
+    assert_eq!(rules.validate("O'Ma-ra Ka'ra-ka"), Ok(()));
+    assert_eq!(
+        rules.validate("O'Ma'ra Character"),
+        Err(NameError::Punctuation)
+    );
+    assert_eq!(rules.validate("Test Ka-ra-ka"), Err(NameError::Punctuation));
+}
+
+#[test]
+fn capitalisation() {
+    let rules = rules();
+    assert_eq!(
+        rules.validate("test Character"),
+        Err(NameError::Capitalisation)
+    );
+    assert_eq!(
+        rules.validate("Test character"),
+        Err(NameError::Capitalisation)
+    );
+    assert_eq!(
+        rules.validate("TEst Character"),
+        Err(NameError::Capitalisation)
+    );
+    assert_eq!(
+        rules.validate("Test CharActer"),
+        Err(NameError::Capitalisation)
+    );
+}
+
+#[test]
+fn banned_words_ignore_case_and_the_space() {
+    let rules = rules();
+    assert_eq!(rules.validate("Badger Test"), Err(NameError::BannedWord));
+    assert_eq!(rules.validate("Test Worse"), Err(NameError::BannedWord));
+    // "worse" split across the forename and surname
+    assert_eq!(rules.validate("Tewo Rse"), Err(NameError::BannedWord));
+}
+
+#[test]
+fn reserved_names_ignore_case() {
+    let rules = rules();
+    assert_eq!(rules.validate("Reserved Name"), Err(NameError::Reserved));
+    // capitalised differently, but then it also breaks the game's own rules
+    assert_eq!(
+        rules.validate("Reserved NAme"),
+        Err(NameError::Capitalisation)
+    );
+    assert_eq!(rules.validate("Reserved Names"), Ok(()));
+
+    let shouting = NameRules::new(&[], &["RESERVED NAME".to_string()]);
+    assert_eq!(shouting.validate("Reserved Name"), Err(NameError::Reserved));
+}
@@ -0,0 +1,498 @@
+use binrw::{BinRead, BinWrite};
+use brokefish::Brokefish;
+use sapphire_protocol::{
+    ipc::Direction,
+    packets::{PacketHeader, PacketSegmentHeader, SegmentType},
+};
+use std::{collections::VecDeque, error::Error, io::Cursor, mem::size_of};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+pub type Result<T> = std::result::Result<T, Box<dyn Error>>;
+
+pub const IPC_HEADER_SIZE: usize = 0x10;
+pub const ENCRYPTION_INIT_SIZE: usize = 0x280;
+pub const ENCRYPTION_INIT_RESPONSE: u16 = 0x0a;
+
+// client opcodes
+pub const REQ_CHAR_LIST: u16 = 0x03;
+pub const REQ_ENTER_WORLD: u16 = 0x04;
+pub const CLIENT_VERSION_INFO: u16 = 0x05;
+pub const REQ_CHAR_DELETE: u16 = 0x0a;
+
+// server opcodes
+pub const LOBBY_ERROR: u16 = 0x02;
+pub const SERVICE_ACCOUNT_LIST: u16 = 0x0c;
+pub const CHAR_LIST: u16 = 0x0d;
+pub const CHAR_CREATE: u16 = 0x0e;
+pub const ENTER_WORLD: u16 = 0x0f;
+pub const SERVER_LIST: u16 = 0x15;
+pub const RETAINER_LIST: u16 = 0x17;
+
+// the key derivation, encryption and framing below are worked out the same way the client does
+// them, kept apart from sapphire_protocol's so a mistake in one doesn't hide in the other.
+// they're only here to check the lobby against, anything else should use sapphire_protocol's
+fn derive_key(key: u32, key_phrase: &str, game_version: u16) -> Vec<u8> {
+    let mut base_key = [0; 0x2c];
+    base_key[0..4].copy_from_slice(&0x12345678_u32.to_le_bytes());
+    base_key[4..8].copy_from_slice(&key.to_le_bytes());
+    base_key[8..10].copy_from_slice(&game_version.to_le_bytes());
+
+    let len = key_phrase.len().min(32);
+    base_key[12..12 + len].copy_from_slice(&key_phrase.as_bytes()[0..len]);
+
+    md5::compute(base_key).to_vec()
+}
+
+// we leave the whole blocks before our last 8 bytes encrypted and the rest as it is. the lobby
+// encrypts all but its last 0x10 bytes, padding the last block, and sends zeroes after that
+fn crypt(key: &[u8], direction: Direction, data: &[u8], encrypt: bool) -> Vec<u8> {
+    let bf = Brokefish::new(key);
+    let run = |data: &[u8]| match encrypt {
+        true => bf.encrypt(data),
+        false => bf.decrypt(data),
+    };
+
+    match direction {
+        Direction::ClientToServer => {
+            let end = data.len().saturating_sub(8);
+            let end = end - end % 8;
+            let mut out = run(&data[0..end]);
+            out.extend_from_slice(&data[end..]);
+            out
+        }
+        Direction::ServerToClient => {
+            let end = data.len().saturating_sub(0x10);
+            let mut out = match encrypt {
+                true => run(&data[0..end]),
+                false => {
+                    let mut out = run(&data[0..end.div_ceil(8) * 8]);
+                    out.truncate(end);
+                    out
+                }
+            };
+            out.resize(data.len(), 0);
+            out
+        }
+    }
+}
+
+fn encrypt_ipc(key: &[u8], direction: Direction, data: &[u8]) -> Vec<u8> {
+    crypt(key, direction, data, true)
+}
+
+fn decrypt_ipc(key: &[u8], direction: Direction, data: &[u8]) -> Vec<u8> {
+    crypt(key, direction, data, false)
+}
+
+// the segments of one whole frame, as they were sent
+fn split_frame(frame: &[u8]) -> Result<Vec<Segment>> {
+    let mut cursor = Cursor::new(frame);
+    let header = PacketHeader::read(&mut cursor)?;
+
+    let mut segments = Vec::new();
+    for _ in 0..header.count {
+        let segment_header = PacketSegmentHeader::read(&mut cursor)?;
+        let start = cursor.position() as usize;
+        let data_size = (segment_header.size as usize)
+            .checked_sub(size_of::<PacketSegmentHeader>())
+            .ok_or("segment smaller than its header")?;
+        let data = frame
+            .get(start..start + data_size)
+            .ok_or("segment runs past the end of its frame")?
+            .to_vec();
+        cursor.set_position((start + data_size) as u64);
+
+        segments.push(Segment {
+            header: segment_header,
+            data,
+        });
+    }
+
+    Ok(segments)
+}
+
+pub struct Segment {
+    pub header: PacketSegmentHeader,
+    pub data: Vec<u8>,
+}
+
+pub struct Ipc {
+    pub opcode: u16,
+    // the 16 byte header as sent, timestamp and all
+    pub header: Vec<u8>,
+    // everything after the header
+    pub data: Vec<u8>,
+}
+
+pub struct FakeClient<S> {
+    stream: S,
+    pub game_version: u16,
+    key: Option<Vec<u8>>,
+    // segments from frames we've read but nobody has asked for yet
+    pending: VecDeque<Segment>,
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> FakeClient<S> {
+    pub fn new(stream: S, game_version: u16) -> FakeClient<S> {
+        FakeClient {
+            stream,
+            game_version,
+            key: None,
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn key(&self) -> Option<&[u8]> {
+        self.key.as_deref()
+    }
+
+    pub async fn send_segment(&mut self, segment_type: u16, data: &[u8]) -> Result<()> {
+        let size = size_of::<PacketHeader>() + size_of::<PacketSegmentHeader>() + data.len();
+        let header = PacketHeader {
+            unknown_0: 0,
+            unknown_8: 0,
+            timestamp: 0,
+            size: size as u32,
+            connection_type: 0,
+            count: 1,
+            unknown_20: 1,
+            is_compressed: 0,
+            unknown_24: 0,
+            uncompressed_size: 0,
+        };
+
+        let mut frame = Cursor::new(Vec::new());
+        header.write_to(&mut frame)?;
+        PacketSegmentHeader::new(segment_type, data.len() as u32, 0, 0).write_to(&mut frame)?;
+
+        let mut frame = frame.into_inner();
+        frame.extend_from_slice(data);
+        self.stream.write_all(&frame).await?;
+
+        Ok(())
+    }
+
+    async fn recv_frame(&mut self) -> Result<()> {
+        let mut frame = vec![0; size_of::<PacketHeader>()];
+        self.stream.read_exact(&mut frame).await?;
+        let header = PacketHeader::read(&mut Cursor::new(&frame))?;
+
+        frame.resize(header.size as usize, 0);
+        self.stream
+            .read_exact(&mut frame[size_of::<PacketHeader>()..])
+            .await?;
+
+        self.pending.extend(split_frame(&frame)?);
+        Ok(())
+    }
+
+    // for replaying frames exactly as they were recorded
+    pub async fn send_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.stream.write_all(frame).await?;
+        Ok(())
+    }
+
+    pub async fn recv_segment(&mut self) -> Result<Segment> {
+        while self.pending.is_empty() {
+            self.recv_frame().await?;
+        }
+        Ok(self.pending.pop_front().expect("just checked"))
+    }
+
+    // the key and phrase are ours to pick, the lobby works out the same key from them
+    pub async fn encryption_init(&mut self, key_phrase: &str, key: u32) -> Result<Segment> {
+        let mut data = vec![0; ENCRYPTION_INIT_SIZE];
+        let phrase = key_phrase.as_bytes();
+        let len = phrase.len().min(32);
+        data[36..36 + len].copy_from_slice(&phrase[0..len]);
+        data[100..104].copy_from_slice(&key.to_le_bytes());
+
+        self.send_segment(SegmentType::EncryptionInit as u16, &data)
+            .await?;
+        self.key = Some(derive_key(key, key_phrase, self.game_version));
+
+        let reply = self.recv_segment().await?;
+        if reply.header.segment_type != ENCRYPTION_INIT_RESPONSE {
+            return Err(format!(
+                "expected an encryption init response, got segment type {}",
+                reply.header.segment_type
+            )
+            .into());
+        }
+        Ok(reply)
+    }
+
+    // pads to whole blocks like the real client
+    pub async fn send_ipc(&mut self, opcode: u16, payload: &[u8]) -> Result<()> {
+        let mut data = vec![0; IPC_HEADER_SIZE];
+        data[0..2].copy_from_slice(&0x14_u16.to_le_bytes());
+        data[2..4].copy_from_slice(&opcode.to_le_bytes());
+        data.extend_from_slice(payload);
+        data.resize(data.len().div_ceil(8) * 8, 0);
+
+        let data = match &self.key {
+            Some(key) => encrypt_ipc(key, Direction::ClientToServer, &data),
+            None => data,
+        };
+        self.send_segment(SegmentType::Ipc as u16, &data).await
+    }
+
+    // answers any keepalives the lobby sends while we wait
+    pub async fn recv_ipc(&mut self) -> Result<Ipc> {
+        loop {
+            let segment = self.recv_segment().await?;
+            match SegmentType::try_from(segment.header.segment_type) {
+                Ok(SegmentType::Ipc) => {
+                    let data = match &self.key {
+                        Some(key) => decrypt_ipc(key, Direction::ServerToClient, &segment.data),
+                        None => segment.data,
+                    };
+                    if data.len() < IPC_HEADER_SIZE {
+                        return Err("IPC shorter than its header".into());
+                    }
+
+                    return Ok(Ipc {
+                        opcode: u16::from_le_bytes([data[2], data[3]]),
+                        header: data[0..IPC_HEADER_SIZE].to_vec(),
+                        data: data[IPC_HEADER_SIZE..].to_vec(),
+                    });
+                }
+                Ok(SegmentType::KeepAlive) => {
+                    self.send_segment(SegmentType::KeepAliveResponse as u16, &segment.data)
+                        .await?;
+                }
+                _ => {
+                    return Err(format!(
+                        "expected an IPC, got segment type {}",
+                        segment.header.segment_type
+                    )
+                    .into())
+                }
+            }
+        }
+    }
+
+    // like recv_ipc, but anything but the expected opcode is an error
+    pub async fn expect_ipc(&mut self, opcode: u16) -> Result<Ipc> {
+        let ipc = self.recv_ipc().await?;
+        if ipc.opcode != opcode {
+            return Err(format!("expected IPC {:#x}, got {:#x}", opcode, ipc.opcode).into());
+        }
+        Ok(ipc)
+    }
+
+    pub async fn client_version_info(
+        &mut self,
+        seq: u64,
+        session_id: &str,
+        version: &str,
+    ) -> Result<()> {
+        let mut payload = vec![0; 8 + 10 + 0x40 + 0x80];
+        payload[0..8].copy_from_slice(&seq.to_le_bytes());
+        write_c_string(&mut payload[18..18 + 0x40], session_id);
+        write_c_string(&mut payload[18 + 0x40..], version);
+
+        self.send_ipc(CLIENT_VERSION_INFO, &payload).await
+    }
+
+    pub async fn req_char_list(&mut self, seq: u64) -> Result<()> {
+        self.send_ipc(REQ_CHAR_LIST, &seq.to_le_bytes()).await
+    }
+
+    pub async fn req_char_delete(&mut self, seq: u64, content_id: u64, name: &str) -> Result<()> {
+        let mut payload = vec![0; 8 + 8 + 12 + 0x20];
+        payload[0..8].copy_from_slice(&seq.to_le_bytes());
+        payload[8..16].copy_from_slice(&content_id.to_le_bytes());
+        write_c_string(&mut payload[28..], name);
+
+        self.send_ipc(REQ_CHAR_DELETE, &payload).await
+    }
+
+    pub async fn req_enter_world(&mut self, seq: u64, content_id: u64) -> Result<()> {
+        let mut payload = seq.to_le_bytes().to_vec();
+        payload.extend_from_slice(&content_id.to_le_bytes());
+
+        self.send_ipc(REQ_ENTER_WORLD, &payload).await
+    }
+}
+
+pub fn write_c_string(buf: &mut [u8], s: &str) {
+    let len = s.len().min(buf.len() - 1);
+    buf[0..len].copy_from_slice(&s.as_bytes()[0..len]);
+    buf[len..].fill(0);
+}
+
+// reads little endian fields out of a reply at fixed offsets
+struct Fields<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Fields<'a> {
+    fn bytes(&self, offset: usize, len: usize) -> Result<&'a [u8]> {
+        self.data
+            .get(offset..offset + len)
+            .ok_or_else(|| format!("reply too short for {} bytes at {:#x}", len, offset).into())
+    }
+
+    fn u8(&self, offset: usize) -> Result<u8> {
+        Ok(self.bytes(offset, 1)?[0])
+    }
+
+    fn u16(&self, offset: usize) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.bytes(offset, 2)?.try_into()?))
+    }
+
+    fn u32(&self, offset: usize) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4)?.try_into()?))
+    }
+
+    fn u64(&self, offset: usize) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(offset, 8)?.try_into()?))
+    }
+
+    fn c_string(&self, offset: usize, len: usize) -> Result<String> {
+        let bytes = self.bytes(offset, len)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(len);
+        Ok(String::from_utf8_lossy(&bytes[0..end]).into_owned())
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct LobbyErrorReply {
+    pub seq: u64,
+    pub error_id: u32,
+    pub param: u32,
+    pub message_id: u16,
+}
+
+impl LobbyErrorReply {
+    pub fn parse(data: &[u8]) -> Result<LobbyErrorReply> {
+        let fields = Fields { data };
+        Ok(LobbyErrorReply {
+            seq: fields.u64(0)?,
+            error_id: fields.u32(8)?,
+            param: fields.u32(12)?,
+            message_id: fields.u16(16)?,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ServiceAccount {
+    pub id: u32,
+    pub index: u32,
+    pub name: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ServiceAccountList {
+    pub seq: u64,
+    pub counter: u8,
+    pub accounts: Vec<ServiceAccount>,
+}
+
+impl ServiceAccountList {
+    const ACCOUNTS: usize = 13;
+    const ACCOUNT_SIZE: usize = 0x50;
+
+    pub fn parse(data: &[u8]) -> Result<ServiceAccountList> {
+        let fields = Fields { data };
+        let len = fields.u8(9)? as usize;
+
+        let mut accounts = Vec::new();
+        for i in 0..len {
+            let offset = Self::ACCOUNTS + i * Self::ACCOUNT_SIZE;
+            accounts.push(ServiceAccount {
+                id: fields.u32(offset)?,
+                index: fields.u32(offset + 8)?,
+                name: fields.c_string(offset + 12, 0x44)?,
+            });
+        }
+
+        Ok(ServiceAccountList {
+            seq: fields.u64(0)?,
+            counter: fields.u8(8)?,
+            accounts,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CharacterEntry {
+    pub id: u32,
+    pub content_id: u64,
+    pub index: u32,
+    pub world_id: u16,
+    pub current_world_id: u16,
+    pub name: String,
+    pub world_name: String,
+    pub current_world_name: String,
+    pub detail_json: String,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct CharList {
+    pub seq: u64,
+    pub counter: u8,
+    pub max_characters_on_world: u16,
+    pub entitled_expansion: u32,
+    pub characters: Vec<CharacterEntry>,
+}
+
+impl CharList {
+    const CHARACTERS: usize = 92;
+    const CHARACTER_SIZE: usize = 1179;
+
+    pub fn parse(data: &[u8]) -> Result<CharList> {
+        let fields = Fields { data };
+        let len = fields.u8(9)? as usize;
+
+        let mut characters = Vec::new();
+        for i in 0..len {
+            let offset = Self::CHARACTERS + i * Self::CHARACTER_SIZE;
+            characters.push(CharacterEntry {
+                id: fields.u32(offset)?,
+                content_id: fields.u64(offset + 8)?,
+                index: fields.u32(offset + 16)?,
+                world_id: fields.u16(offset + 24)?,
+                current_world_id: fields.u16(offset + 26)?,
+                name: fields.c_string(offset + 37, 0x20)?,
+                world_name: fields.c_string(offset + 69, 0x20)?,
+                current_world_name: fields.c_string(offset + 101, 0x20)?,
+                detail_json: fields.c_string(offset + 133, 1050)?,
+            });
+        }
+
+        Ok(CharList {
+            seq: fields.u64(0)?,
+            counter: fields.u8(8)?,
+            max_characters_on_world: fields.u16(80)?,
+            entitled_expansion: fields.u32(84)?,
+            characters,
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct EnterWorld {
+    pub seq: u64,
+    pub character_id: u32,
+    pub content_id: u64,
+    pub session_id: String,
+    pub port: u16,
+    pub host: String,
+}
+
+impl EnterWorld {
+    pub fn parse(data: &[u8]) -> Result<EnterWorld> {
+        let fields = Fields { data };
+        Ok(EnterWorld {
+            seq: fields.u64(0)?,
+            character_id: fields.u32(8)?,
+            content_id: fields.u64(16)?,
+            session_id: fields.c_string(28, 66)?,
+            port: fields.u16(94)?,
+            host: fields.c_string(96, 48)?,
+        })
+    }
+}
@@ -0,0 +1,283 @@
+use fake_client::{
+    CharList, FakeClient, LobbyErrorReply, ServiceAccountList, CHAR_LIST, LOBBY_ERROR,
+    RETAINER_LIST, SERVER_LIST, SERVICE_ACCOUNT_LIST,
+};
+use lobby::{
+    capture::read_capture,
+    client::Client,
+    config::{Config, WorldConfig},
+    connections::Connections,
+    worlds::WorldRegistry,
+};
+use std::sync::Arc;
+use storage::{
+    AccountRepository, CharacterRepository, ServiceAccountRepository, SessionRepository,
+    SqliteStorage,
+};
+use tokio::io::{duplex, DuplexStream};
+
+const SESSION_ID: &str = "0123456789abcdef0123456789abcdef0123456789abcdef0123456789ab";
+const GAME_VERSION: u16 = 6100;
+
+struct Fixture {
+    db: Arc<SqliteStorage>,
+    service_accounts: Vec<u32>,
+}
+
+fn fixture() -> Fixture {
+    let db = Arc::new(SqliteStorage::open_in_memory().unwrap());
+    let account_id = db.add_account("test").unwrap();
+    db.add_session(account_id, SESSION_ID).unwrap();
+
+    let service_accounts = vec![
+        db.add_service_account(account_id, "FINAL FANTASY XIV")
+            .unwrap(),
+        db.add_service_account(account_id, "FINAL FANTASY XIV 2")
+            .unwrap(),
+    ];
+
+    Fixture {
+        db,
+        service_accounts,
+    }
+}
+
+fn connect(db: Arc<SqliteStorage>) -> FakeClient<DuplexStream> {
+    connect_with(db, Config::default())
+}
+
+fn connect_with(db: Arc<SqliteStorage>, config: Config) -> FakeClient<DuplexStream> {
+    connect_as(db, config, GAME_VERSION)
+}
+
+fn connect_as(
+    db: Arc<SqliteStorage>,
+    config: Config,
+    game_version: u16,
+) -> FakeClient<DuplexStream> {
+    let config = Arc::new(Config {
+        worlds: vec![WorldConfig {
+            id: 1,
+            name: "Ultros".to_string(),
+            data_centre: "Sapphire".to_string(),
+            host: "127.0.0.1".to_string(),
+            port: 54992,
+            congested_at: 500,
+            capacity: 600,
+            closed_for_creation: false,
+        }],
+        ..config
+    });
+    let worlds = WorldRegistry::new(config.clone());
+
+    let (ours, theirs) = duplex(0x10000);
+    let mut client = Client::new(theirs, db, config, worlds, Connections::default());
+    tokio::spawn(async move { client.handle().await });
+
+    FakeClient::new(ours, game_version)
+}
+
+async fn log_in(client: &mut FakeClient<DuplexStream>) -> ServiceAccountList {
+    client
+        .encryption_init("test key phrase", 0xdeadbeef)
+        .await
+        .unwrap();
+    client
+        .client_version_info(1, SESSION_ID, "2022.05.19.0000.0000")
+        .await
+        .unwrap();
+
+    let ipc = client.expect_ipc(SERVICE_ACCOUNT_LIST).await.unwrap();
+    ServiceAccountList::parse(&ipc.data).unwrap()
+}
+
+#[tokio::test]
+async fn encryption_init_is_answered_in_the_clear() {
+    let mut client = connect(fixture().db);
+    let reply = client
+        .encryption_init("test key phrase", 0xdeadbeef)
+        .await
+        .unwrap();
+
+    let mut expected = vec![0; 0x290];
+    expected[0..4].copy_from_slice(&0xe0003c2a_u32.to_le_bytes());
+    assert_eq!(reply.data, expected);
+}
+
+#[tokio::test]
+async fn service_account_list_matches_byte_for_byte() {
+    let fixture = fixture();
+    let mut client = connect(fixture.db.clone());
+
+    client
+        .encryption_init("test key phrase", 0xdeadbeef)
+        .await
+        .unwrap();
+    client
+        .client_version_info(1, SESSION_ID, "2022.05.19.0000.0000")
+        .await
+        .unwrap();
+    let ipc = client.expect_ipc(SERVICE_ACCOUNT_LIST).await.unwrap();
+
+    // everything but the timestamp in the IPC header is fixed
+    assert_eq!(&ipc.header[0..8], &[0, 0, 0x0c, 0, 0, 0, 0, 0]);
+    assert_eq!(&ipc.header[12..16], &[0; 4]);
+
+    let mut expected = vec![0; 13 + 8 * 0x50];
+    expected[0..8].copy_from_slice(&1_u64.to_le_bytes());
+    expected[8] = 1; // first and last packet
+    expected[9] = 2;
+    expected[10] = 3;
+    expected[11] = 0x99;
+    for (i, (id, name)) in fixture
+        .service_accounts
+        .iter()
+        .zip(["FINAL FANTASY XIV", "FINAL FANTASY XIV 2"])
+        .enumerate()
+    {
+        let offset = 13 + i * 0x50;
+        expected[offset..offset + 4].copy_from_slice(&id.to_le_bytes());
+        expected[offset + 8..offset + 12].copy_from_slice(&(i as u32).to_le_bytes());
+        expected[offset + 12..offset + 12 + name.len()].copy_from_slice(name.as_bytes());
+    }
+    assert_eq!(ipc.data, expected);
+
+    let list = ServiceAccountList::parse(&ipc.data).unwrap();
+    assert_eq!(list.accounts[1].name, "FINAL FANTASY XIV 2");
+    assert_eq!(list.accounts[1].index, 1);
+}
+
+#[tokio::test]
+async fn unknown_session_is_rejected() {
+    let mut client = connect(fixture().db);
+    client
+        .encryption_init("test key phrase", 0xdeadbeef)
+        .await
+        .unwrap();
+    client
+        .client_version_info(5, "not a session", "2022.05.19.0000.0000")
+        .await
+        .unwrap();
+
+    let ipc = client.expect_ipc(LOBBY_ERROR).await.unwrap();
+    assert_eq!(
+        LobbyErrorReply::parse(&ipc.data).unwrap(),
+        LobbyErrorReply {
+            seq: 5,
+            error_id: 5006,
+            param: 0,
+            message_id: 13001,
+        }
+    );
+}
+
+#[tokio::test]
+async fn unsupported_game_versions_are_told_to_update() {
+    let mut client = connect_as(fixture().db, Config::default(), 6200);
+    client
+        .encryption_init("test key phrase", 0xdeadbeef)
+        .await
+        .unwrap();
+    client
+        .client_version_info(3, SESSION_ID, "2022.05.19.0000.0000")
+        .await
+        .unwrap();
+
+    // in 6200's key, which the client can read
+    let ipc = client.expect_ipc(LOBBY_ERROR).await.unwrap();
+    assert_eq!(
+        LobbyErrorReply::parse(&ipc.data).unwrap(),
+        LobbyErrorReply {
+            seq: 3,
+            error_id: 1012,
+            param: 0,
+            message_id: 13101,
+        }
+    );
+}
+
+#[tokio::test]
+async fn char_list_follows_the_world_list() {
+    let fixture = fixture();
+    let account_id = 1;
+    let character = fixture
+        .db
+        .add_character(account_id, 1, "Test Character")
+        .unwrap();
+
+    let mut client = connect(fixture.db.clone());
+    log_in(&mut client).await;
+
+    client.req_char_list(2).await.unwrap();
+    client.expect_ipc(SERVER_LIST).await.unwrap();
+    let ipc = client.expect_ipc(CHAR_LIST).await.unwrap();
+    client.expect_ipc(RETAINER_LIST).await.unwrap();
+
+    let list = CharList::parse(&ipc.data).unwrap();
+    assert_eq!(list.seq, 2);
+    assert_eq!(list.counter, 1);
+    assert_eq!(list.max_characters_on_world, 8);
+    assert_eq!(list.entitled_expansion, 4);
+
+    assert_eq!(list.characters.len(), 1);
+    let entry = &list.characters[0];
+    assert_eq!(entry.id, character.character_id);
+    assert_eq!(entry.content_id, character.content_id);
+    assert_eq!(entry.index, 0);
+    assert_eq!(entry.world_id, 1);
+    assert_eq!(entry.name, "Test Character");
+    assert_eq!(entry.world_name, "Ultros");
+    assert_eq!(entry.current_world_name, "Ultros");
+}
+
+#[tokio::test]
+async fn captures_record_both_directions_decrypted() {
+    let dir = std::env::temp_dir().join(format!("lobby-capture-test-{}", std::process::id()));
+    let config = Config {
+        capture_dir: Some(dir.to_string_lossy().into_owned()),
+        ..Config::default()
+    };
+
+    let mut client = connect_with(fixture().db, config);
+    log_in(&mut client).await;
+
+    // each record is flushed before its frame goes out, so it's all there already
+    let path = std::fs::read_dir(&dir)
+        .unwrap()
+        .next()
+        .unwrap()
+        .unwrap()
+        .path();
+    let records = read_capture(&path).unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let summary: Vec<_> = records
+        .iter()
+        .map(|r| (r.is_from_client(), r.is_frame(), r.segment_type))
+        .collect();
+    assert_eq!(
+        summary,
+        [
+            (true, true, 0),
+            (true, false, 9),
+            (false, false, 0x0a),
+            (false, true, 0),
+            (true, true, 0),
+            (true, false, 3),
+            (false, false, 3),
+            (false, true, 0),
+        ]
+    );
+
+    // the decrypted client IPC is readable, the frame it came in isn't
+    let version_info = &records[5];
+    assert_eq!(&version_info.data[2..4], &[0x05, 0x00]);
+    assert!(!records[4]
+        .data
+        .windows(SESSION_ID.len())
+        .any(|w| w == SESSION_ID.as_bytes()));
+    assert!(version_info
+        .data
+        .windows(SESSION_ID.len())
+        .any(|w| w == SESSION_ID.as_bytes()));
+}
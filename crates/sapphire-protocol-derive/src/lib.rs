@@ -0,0 +1,286 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{quote, ToTokens};
+use syn::{
+    parse::{Parse, ParseStream, Parser},
+    parse_macro_input, parse_quote,
+    punctuated::Punctuated,
+    Attribute, Error, Expr, Fields, Ident, ItemStruct, Token, Type,
+};
+
+// every argument the macro takes looks like `name = value`
+struct Arg {
+    name: Ident,
+    value: Expr,
+}
+
+impl Parse for Arg {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse()?;
+        input.parse::<Token![=]>()?;
+        Ok(Arg {
+            name,
+            value: input.parse()?,
+        })
+    }
+}
+
+type Args = Punctuated<Arg, Token![,]>;
+
+fn set(slot: &mut Option<Expr>, arg: Arg) -> syn::Result<()> {
+    if slot.is_some() {
+        return Err(Error::new_spanned(&arg.name, "given more than once"));
+    }
+    *slot = Some(arg.value);
+    Ok(())
+}
+
+#[derive(Default)]
+struct StructArgs {
+    name: Option<Expr>,
+    direction: Option<Expr>,
+    size: Option<Expr>,
+}
+
+impl StructArgs {
+    fn new(args: Args) -> syn::Result<StructArgs> {
+        let mut out = StructArgs::default();
+        for arg in args {
+            match arg.name.to_string().as_str() {
+                "name" => set(&mut out.name, arg)?,
+                "direction" => set(&mut out.direction, arg)?,
+                "size" => set(&mut out.size, arg)?,
+                _ => {
+                    return Err(Error::new_spanned(
+                        &arg.name,
+                        "expected name, direction or size",
+                    ))
+                }
+            }
+        }
+        Ok(out)
+    }
+}
+
+#[derive(Default)]
+struct FieldArgs {
+    offset: Option<Expr>,
+}
+
+impl FieldArgs {
+    // takes the #[ipc(...)] attributes off a field, they mean nothing to anyone else
+    fn take(attrs: &mut Vec<Attribute>) -> syn::Result<FieldArgs> {
+        let mut out = FieldArgs::default();
+        let mut rest = Vec::new();
+
+        for attr in attrs.drain(..) {
+            if !attr.path.is_ident("ipc") {
+                rest.push(attr);
+                continue;
+            }
+            for arg in attr.parse_args_with(Args::parse_terminated)? {
+                match arg.name.to_string().as_str() {
+                    "offset" => set(&mut out.offset, arg)?,
+                    _ => return Err(Error::new_spanned(&arg.name, "expected offset")),
+                }
+            }
+        }
+
+        *attrs = rest;
+        Ok(out)
+    }
+}
+
+// arrays past 32 elements don't implement Default, so they're built an element at a time
+fn default_for(ty: &Type) -> TokenStream2 {
+    match ty {
+        Type::Array(array) => {
+            let element = default_for(&array.elem);
+            quote!(::core::array::from_fn(|_| #element))
+        }
+        _ => quote!(::core::default::Default::default()),
+    }
+}
+
+fn direction_path(expr: &Expr) -> syn::Result<TokenStream2> {
+    let direction = match expr {
+        Expr::Path(path) if path.path.is_ident("client_to_server") => quote!(ClientToServer),
+        Expr::Path(path) if path.path.is_ident("server_to_client") => quote!(ServerToClient),
+        _ => {
+            return Err(Error::new_spanned(
+                expr,
+                "expected client_to_server or server_to_client",
+            ))
+        }
+    };
+    Ok(quote!(::sapphire_protocol::ipc::Direction::#direction))
+}
+
+fn expand(args: Args, mut item: ItemStruct) -> syn::Result<TokenStream2> {
+    let args = StructArgs::new(args)?;
+    if !item.generics.params.is_empty() {
+        return Err(Error::new_spanned(
+            &item.generics,
+            "IPC structs can't be generic",
+        ));
+    }
+
+    let name = item.ident.clone();
+    let fields = match &mut item.fields {
+        Fields::Named(fields) => &mut fields.named,
+        _ => return Err(Error::new_spanned(&name, "IPC structs need named fields")),
+    };
+
+    // where the previous field ended, as an expression since only the compiler knows the sizes
+    let mut end = quote!(0);
+    let mut checks = Vec::new();
+    let mut defaults = Vec::new();
+    for field in fields.iter_mut() {
+        let field_args = FieldArgs::take(&mut field.attrs)?;
+        let ident = field.ident.clone().expect("named fields have names");
+        let ty = field.ty.clone();
+
+        // the gap between the previous field and this one is padding
+        if let Some(offset) = &field_args.offset {
+            let pad = quote!((#offset) - (#end));
+            field.attrs.push(parse_quote!(#[br(pad_before = #pad)]));
+            field.attrs.push(parse_quote!(#[bw(pad_before = #pad)]));
+
+            let message = format!(
+                "{}.{} at {} overlaps the field before it",
+                name,
+                ident,
+                offset.to_token_stream()
+            );
+            checks.push(quote!(
+                const _: () = assert!((#offset) >= (#end), #message);
+            ));
+            end = quote!((#offset));
+        }
+
+        end = quote!(#end + <#ty as ::sapphire_protocol::ipc::WireSize>::SIZE);
+
+        let default = default_for(&ty);
+        defaults.push(quote!(#ident: #default));
+    }
+
+    // and so is whatever the fields leave at the end
+    let size = match args.size {
+        Some(size) => {
+            let pad = quote!((#size) - (#end));
+            if let Some(last) = fields.last_mut() {
+                last.attrs.push(parse_quote!(#[br(pad_after = #pad)]));
+                last.attrs.push(parse_quote!(#[bw(pad_after = #pad)]));
+            }
+
+            let message = format!(
+                "{}'s fields don't fit in the {} bytes ipc_struct was told it is",
+                name,
+                size.to_token_stream()
+            );
+            checks.push(quote!(
+                const _: () = assert!((#size) >= (#end), #message);
+            ));
+            quote!((#size))
+        }
+        None => end,
+    };
+
+    let packet = match (args.name, args.direction) {
+        (Some(ipc_name), Some(direction)) => {
+            let direction = direction_path(&direction)?;
+            quote! {
+                impl ::sapphire_protocol::ipc::IpcPacket for #name {
+                    const NAME: &'static str = #ipc_name;
+                    const DIRECTION: ::sapphire_protocol::ipc::Direction = #direction;
+                }
+            }
+        }
+        (None, None) => quote!(),
+        _ => {
+            return Err(Error::new_spanned(
+                &name,
+                "a name needs a direction, and a direction needs a name",
+            ))
+        }
+    };
+
+    Ok(quote! {
+        #[derive(::binrw::BinRead, ::binrw::BinWrite)]
+        // decided by the crate using this, whose serde feature turns on sapphire_protocol's. the
+        // derives go through sapphire_protocol's re-export, so it needn't have serde itself
+        #[cfg_attr(
+            feature = "serde",
+            derive(::sapphire_protocol::serde::Serialize, ::sapphire_protocol::serde::Deserialize),
+            serde(crate = "::sapphire_protocol::serde")
+        )]
+        #item
+
+        impl ::core::default::Default for #name {
+            fn default() -> Self {
+                #name {
+                    #(#defaults,)*
+                }
+            }
+        }
+
+        impl ::sapphire_protocol::ipc::WireSize for #name {
+            const SIZE: usize = #size;
+        }
+
+        #(#checks)*
+
+        #packet
+    })
+}
+
+/// Declares a struct as IPC data: something that goes after an
+/// [`IPCHeader`](../sapphire_protocol/ipc/struct.IPCHeader.html), or a part of one.
+///
+/// The struct gets `BinRead`, `BinWrite`, `Default` and `WireSize`, so the crate using it needs
+/// `binrw` as a dependency and shouldn't derive those itself. Every field's type has to implement
+/// `WireSize`, which the integer types, `FixedStr`s, arrays of them and other `ipc_struct`s do.
+/// With a `serde` feature of its own turned on, which has to turn on sapphire-protocol's, the
+/// crate using it gets `Serialize` and `Deserialize` too.
+///
+/// Padding is never a field. It's worked out from where the fields are said to be, so nothing
+/// has to be counted by hand.
+///
+/// On the struct, all optional:
+///
+/// - `size = <bytes>` is how many bytes the struct takes up. Anything after the last field is
+///   padding, and the build fails if the fields don't fit.
+/// - `name = "<name>"` and `direction = client_to_server` or `server_to_client` implement
+///   `IpcPacket`, for structs that are a whole IPC rather than a part of one. The name is what
+///   its opcode is looked up by in an `OpcodeTable`.
+///
+/// On fields, in `#[ipc(...)]`:
+///
+/// - `offset = <bytes>` is where the field starts, from the start of the struct. The gap since
+///   the end of the field before it is zeroes on the wire, skipped over when reading, and the
+///   build fails if the two overlap. Fields without one follow straight on from the last.
+///
+/// ```ignore
+/// #[ipc_struct(size = 0x54)]
+/// pub struct IPCServer {
+///     pub id: u16,
+///     pub index: u16,
+///     pub flags: u32,
+///     #[ipc(offset = 0x0c)]
+///     pub icon: u32,
+///     #[ipc(offset = 0x14)]
+///     pub name: FixedStr<0x40>,
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn ipc_struct(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = match Args::parse_terminated.parse(attr) {
+        Ok(args) => args,
+        Err(e) => return e.into_compile_error().into(),
+    };
+    let item = parse_macro_input!(item as ItemStruct);
+
+    expand(args, item)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
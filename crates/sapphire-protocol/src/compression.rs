@@ -0,0 +1,78 @@
+//! Compression of a frame's segments.
+//!
+//! When a frame is compressed, everything after its [`PacketHeader`](crate::packets::PacketHeader)
+//! is compressed as one block. The header's `is_compressed` says how, and its
+//! `uncompressed_size` how big the segments are once they're back.
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use num_enum::TryFromPrimitive;
+use std::{
+    error::Error,
+    io::{Read, Write},
+};
+
+/// How a frame's segments are compressed, the `is_compressed` byte of its header.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, TryFromPrimitive)]
+#[repr(u8)]
+pub enum CompressionType {
+    None = 0,
+    Zlib = 1,
+    /// Used by newer clients. There's no Rust implementation of it to lean on, so frames using it
+    /// are refused.
+    Oodle = 2,
+}
+
+/// Compresses a frame's segments.
+pub fn compress(compression: CompressionType, data: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    match compression {
+        CompressionType::None => Ok(data.to_vec()),
+        CompressionType::Zlib => {
+            let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        CompressionType::Oodle => Err("oodle compression isn't supported".into()),
+    }
+}
+
+/// Decompresses a frame's segments, refusing to produce more than `max_size` bytes.
+///
+/// The size the frame header claims is only used to check the result, so a frame can't make us
+/// allocate more than `max_size` however it's put together.
+pub fn decompress(
+    compression: CompressionType,
+    data: &[u8],
+    uncompressed_size: usize,
+    max_size: usize,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    if uncompressed_size > max_size {
+        return Err(format!(
+            "frame claims {} bytes uncompressed, more than the {} allowed",
+            uncompressed_size, max_size
+        )
+        .into());
+    }
+
+    let data = match compression {
+        CompressionType::None => data.to_vec(),
+        CompressionType::Zlib => {
+            // one byte more than we'd take, to tell a frame that's too big from one that fits
+            let mut out = Vec::new();
+            ZlibDecoder::new(data)
+                .take(max_size as u64 + 1)
+                .read_to_end(&mut out)?;
+            out
+        }
+        CompressionType::Oodle => return Err("oodle compression isn't supported".into()),
+    };
+
+    if data.len() != uncompressed_size {
+        return Err(format!(
+            "frame claims {} bytes uncompressed but has {}",
+            uncompressed_size,
+            data.len()
+        )
+        .into());
+    }
+    Ok(data)
+}
@@ -0,0 +1,91 @@
+//! Encryption of IPC segments.
+//!
+//! A connection starts unencrypted. The client's EncryptionInit segment carries a key and a key
+//! phrase, which together with the client's game version make the Brokefish key every IPC after
+//! it is encrypted with, in both directions. Other segment types are never encrypted.
+
+use brokefish::Brokefish;
+
+use crate::ipc::Direction;
+
+/// The key and key phrase from an EncryptionInit segment's data, in that order. `None` if it's
+/// too short to hold them.
+pub fn encryption_init_key(data: &[u8]) -> Option<(&[u8], &[u8])> {
+    Some((data.get(100..104)?, data.get(36..68)?))
+}
+
+/// Makes the Brokefish key for a connection from what [`encryption_init_key`] found.
+///
+/// The game version isn't in the EncryptionInit, so a server that accepts more than one has to
+/// try each until an IPC decrypts to something sensible.
+///
+/// # Panics
+///
+/// If `key` isn't 4 bytes or `key_phrase` isn't 32.
+pub fn derive_key(key: &[u8], key_phrase: &[u8], game_version: u16) -> Vec<u8> {
+    let mut base_key: [u8; 0x2c] = [0; 0x2c];
+    base_key[0] = 0x78;
+    base_key[1] = 0x56;
+    base_key[2] = 0x34;
+    base_key[3] = 0x12;
+    base_key[4..8].copy_from_slice(key);
+    base_key[8..10].copy_from_slice(&game_version.to_le_bytes());
+    base_key[12..44].copy_from_slice(key_phrase);
+
+    md5::compute(base_key).to_vec()
+}
+
+// servers encrypt all but the last 0x10 bytes of an IPC and send zeroes in their place, clients
+// all but the last 8, which go as they are. it's what the lobby has always done, a capture of
+// the real client is what it'd take to change it
+const SERVER_PLAIN_TAIL: usize = 0x10;
+const CLIENT_PLAIN_TAIL: usize = 8;
+
+/// Encrypts an IPC segment's data, IPC header included, the way it's sent in `direction`.
+///
+/// From the server, all but the last 0x10 bytes are encrypted, with the last block padded with
+/// zeroes, and zeroes are sent after it. From the client, all but the last 8 bytes are encrypted,
+/// in whole blocks, and the rest is sent as it is.
+pub fn encrypt_ipc(key: &[u8], direction: Direction, data: &[u8]) -> Vec<u8> {
+    let bf = Brokefish::new(key);
+    match direction {
+        Direction::ServerToClient => {
+            let mut buf = bf.encrypt(&data[0..data.len().saturating_sub(SERVER_PLAIN_TAIL)]);
+            buf.resize(data.len(), 0);
+            buf
+        }
+        Direction::ClientToServer => {
+            let blocks = client_blocks(data.len());
+            let mut buf = bf.encrypt(&data[0..blocks]);
+            buf.extend_from_slice(&data[blocks..]);
+            buf
+        }
+    }
+}
+
+/// Decrypts an IPC segment's data, the reverse of [`encrypt_ipc`]. What the server never
+/// encrypted comes back as zeroes.
+pub fn decrypt_ipc(key: &[u8], direction: Direction, data: &[u8]) -> Vec<u8> {
+    let bf = Brokefish::new(key);
+    match direction {
+        Direction::ServerToClient => {
+            let len = data.len().saturating_sub(SERVER_PLAIN_TAIL);
+            let blocks = len.div_ceil(8) * 8;
+            let mut buf = bf.decrypt(&data[0..blocks]);
+            buf.truncate(len);
+            buf.resize(data.len(), 0);
+            buf
+        }
+        Direction::ClientToServer => {
+            let blocks = client_blocks(data.len());
+            let mut buf = bf.decrypt(&data[0..blocks]);
+            buf.extend_from_slice(&data[blocks..]);
+            buf
+        }
+    }
+}
+
+fn client_blocks(len: usize) -> usize {
+    let len = len.saturating_sub(CLIENT_PLAIN_TAIL);
+    len - len % 8
+}
@@ -0,0 +1,208 @@
+//! Strings that sit in a fixed size, null padded buffer, which is how every name, world and
+//! version string in the protocol is sent.
+
+use binrw::{BinRead, BinWrite};
+use std::{
+    borrow::Cow,
+    error::Error,
+    fmt,
+    str::{self, Utf8Error},
+};
+
+use crate::ipc::WireSize;
+
+/// A null terminated string in a buffer of `N` bytes.
+///
+/// The bytes are kept as they came off the wire, including anything after the null, so a read
+/// and a write give back exactly what was read. Nothing checks they're UTF-8 until they're used:
+/// [`as_str`](Self::as_str) refuses anything that isn't, [`to_string_lossy`](Self::to_string_lossy)
+/// replaces it.
+///
+/// Built from a `str`, there's always room left for the null, so it holds at most
+/// [`CAPACITY`](Self::CAPACITY) bytes. [`new`](Self::new) refuses anything longer,
+/// [`truncated`](Self::truncated) cuts it short on a character boundary.
+///
+/// With the `serde` feature it's serialized as a string, or as all `N` bytes when a string would
+/// leave something out. Formats that aren't human readable, like bincode, always get the bytes,
+/// as they can't tell which of the two they're reading.
+#[derive(BinRead, BinWrite, Clone, PartialEq, Eq, Hash)]
+pub struct FixedStr<const N: usize> {
+    bytes: [u8; N],
+}
+
+impl<const N: usize> FixedStr<N> {
+    /// The longest string that fits, in bytes.
+    pub const CAPACITY: usize = N - 1;
+
+    /// `s` if it fits, otherwise a [`TooLong`] error.
+    pub fn new(s: &str) -> Result<FixedStr<N>, TooLong> {
+        if s.len() > Self::CAPACITY {
+            return Err(TooLong {
+                len: s.len(),
+                capacity: Self::CAPACITY,
+            });
+        }
+        Ok(Self::truncated(s))
+    }
+
+    /// As much of `s` as fits, without splitting a character.
+    pub fn truncated(s: &str) -> FixedStr<N> {
+        let mut len = s.len().min(Self::CAPACITY);
+        while !s.is_char_boundary(len) {
+            len -= 1;
+        }
+
+        let mut bytes = [0; N];
+        bytes[..len].copy_from_slice(&s.as_bytes()[..len]);
+        FixedStr { bytes }
+    }
+
+    /// The bytes before the first null, or all of them if there isn't one.
+    pub fn as_bytes(&self) -> &[u8] {
+        let len = self.bytes.iter().position(|&b| b == 0).unwrap_or(N);
+        &self.bytes[..len]
+    }
+
+    /// The string, if it's UTF-8.
+    pub fn as_str(&self) -> Result<&str, Utf8Error> {
+        str::from_utf8(self.as_bytes())
+    }
+
+    /// The string, with anything that isn't UTF-8 replaced.
+    pub fn to_string_lossy(&self) -> Cow<'_, str> {
+        String::from_utf8_lossy(self.as_bytes())
+    }
+
+    /// Whether there's nothing before the null.
+    pub fn is_empty(&self) -> bool {
+        self.bytes[0] == 0
+    }
+
+    /// The whole buffer, as it goes on the wire.
+    pub fn raw(&self) -> &[u8; N] {
+        &self.bytes
+    }
+}
+
+impl<const N: usize> From<[u8; N]> for FixedStr<N> {
+    fn from(bytes: [u8; N]) -> FixedStr<N> {
+        FixedStr { bytes }
+    }
+}
+
+impl<const N: usize> Default for FixedStr<N> {
+    fn default() -> FixedStr<N> {
+        FixedStr { bytes: [0; N] }
+    }
+}
+
+impl<const N: usize> WireSize for FixedStr<N> {
+    const SIZE: usize = N;
+}
+
+impl<const N: usize> PartialEq<str> for FixedStr<N> {
+    fn eq(&self, other: &str) -> bool {
+        self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl<const N: usize> PartialEq<&str> for FixedStr<N> {
+    fn eq(&self, other: &&str) -> bool {
+        self == *other
+    }
+}
+
+impl<const N: usize> fmt::Debug for FixedStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_string_lossy(), f)
+    }
+}
+
+impl<const N: usize> fmt::Display for FixedStr<N> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.to_string_lossy(), f)
+    }
+}
+
+/// A string given to [`FixedStr::new`] that doesn't fit.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TooLong {
+    pub len: usize,
+    pub capacity: usize,
+}
+
+impl fmt::Display for TooLong {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "a {} byte string doesn't fit in {} bytes",
+            self.len, self.capacity
+        )
+    }
+}
+
+impl Error for TooLong {}
+
+// a string when that says everything about it, otherwise all N bytes so nothing's lost.
+// only human readable formats can say which one they've got, the rest always get the bytes
+#[cfg(feature = "serde")]
+impl<const N: usize> serde::Serialize for FixedStr<N> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if !serializer.is_human_readable() {
+            return serializer.serialize_bytes(&self.bytes);
+        }
+
+        let after_null = &self.bytes[self.as_bytes().len()..];
+        match self.as_str() {
+            Ok(s) if after_null.iter().all(|&b| b == 0) => serializer.serialize_str(s),
+            _ => serializer.collect_seq(self.bytes.iter()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::Deserialize<'de> for FixedStr<N> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            deserializer.deserialize_any(FixedStrVisitor)
+        } else {
+            deserializer.deserialize_bytes(FixedStrVisitor)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+struct FixedStrVisitor<const N: usize>;
+
+#[cfg(feature = "serde")]
+impl<'de, const N: usize> serde::de::Visitor<'de> for FixedStrVisitor<N> {
+    type Value = FixedStr<N>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "a string of at most {} bytes or {} bytes", N - 1, N)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<FixedStr<N>, E> {
+        FixedStr::new(v).map_err(E::custom)
+    }
+
+    fn visit_bytes<E: serde::de::Error>(self, v: &[u8]) -> Result<FixedStr<N>, E> {
+        let bytes = v
+            .try_into()
+            .map_err(|_| E::invalid_length(v.len(), &self))?;
+        Ok(FixedStr { bytes })
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<FixedStr<N>, A::Error> {
+        let mut bytes = [0; N];
+        for (i, byte) in bytes.iter_mut().enumerate() {
+            *byte = seq
+                .next_element()?
+                .ok_or_else(|| serde::de::Error::invalid_length(i, &self))?;
+        }
+        if seq.next_element::<u8>()?.is_some() {
+            return Err(serde::de::Error::invalid_length(N + 1, &self));
+        }
+        Ok(FixedStr { bytes })
+    }
+}
@@ -0,0 +1,96 @@
+//! The header every IPC segment starts with, and helpers for IPC data.
+//!
+//! Opcodes and what follows the header differ between lobby, zone and chat connections, so
+//! they're left to each server, which declares them with [`ipc_struct`](crate::ipc_struct).
+
+use binrw::{BinRead, BinWrite};
+use std::{
+    mem::size_of,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// The first 16 bytes of an IPC segment's data.
+#[derive(BinRead, BinWrite, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IPCHeader {
+    pub reserved: u16,
+    /// The opcode, see [`opcodes`](crate::opcodes).
+    pub ipc_type: u16,
+    pub padding: u16,
+    pub server_id: u16,
+    /// Seconds since the unix epoch.
+    pub timestamp: u32,
+    pub padding1: u32,
+}
+
+impl IPCHeader {
+    /// A header for an IPC of type `ipc_type`, stamped with the current time.
+    pub fn new(server_id: u16, ipc_type: u16) -> IPCHeader {
+        IPCHeader {
+            reserved: 0,
+            ipc_type,
+            padding: 0,
+            server_id,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .expect("time went backwards")
+                .as_secs() as u32,
+            padding1: 0,
+        }
+    }
+}
+
+/// An IPC's opcode and everything after its header. `None` if it's too short to have a header.
+pub fn split_ipc(data: &[u8]) -> Option<(u16, &[u8])> {
+    let body = data.get(size_of::<IPCHeader>()..)?;
+    Some((u16::from_le_bytes([data[2], data[3]]), body))
+}
+
+/// Which way an IPC goes.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Direction {
+    ClientToServer,
+    ServerToClient,
+}
+
+/// A struct that's a whole IPC, implemented by [`ipc_struct`](crate::ipc_struct) when it's
+/// given a name.
+pub trait IpcPacket {
+    /// What it's called in [`OpcodeTable`](crate::opcodes::OpcodeTable)s, which give the
+    /// `ipc_type` its [`IPCHeader`] is sent with.
+    const NAME: &'static str;
+    const DIRECTION: Direction;
+}
+
+/// How many bytes a type takes up on the wire.
+///
+/// Implemented by [`ipc_struct`](crate::ipc_struct) for every struct it's used on, which is how
+/// it checks their sizes at compile time.
+pub trait WireSize {
+    const SIZE: usize;
+}
+
+macro_rules! wire_size {
+    ($($ty:ty),*) => {
+        $(
+            impl WireSize for $ty {
+                const SIZE: usize = size_of::<$ty>();
+            }
+        )*
+    };
+}
+
+wire_size!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl<T: WireSize, const N: usize> WireSize for [T; N] {
+    const SIZE: usize = T::SIZE * N;
+}
+
+/// Reads a string from a fixed size buffer, stopping at the first null.
+///
+/// Anything that isn't UTF-8 is replaced rather than refused, the client isn't picky about what
+/// it puts there.
+pub fn read_c_string(buf: &[u8]) -> String {
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[0..len]).into_owned()
+}
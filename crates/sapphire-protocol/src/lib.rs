@@ -0,0 +1,29 @@
+//! The parts of the FFXIV protocol shared by lobby, zone and chat connections.
+//!
+//! Everything sent over a connection is a frame: a [`packets::PacketHeader`] followed by one or
+//! more segments, each with a [`packets::PacketSegmentHeader`]. IPC segments start with an
+//! [`ipc::IPCHeader`] and, on connections that use it, are encrypted with a key from
+//! [`encryption::derive_key`]. The segments of a frame may be compressed as a whole, see
+//! [`compression`].
+//!
+//! The IPCs themselves are declared with [`ipc_struct`], and their opcodes looked up in
+//! [`opcodes`] tables for the client's game version. Their strings are
+//! [`FixedStr`](fixed_str::FixedStr)s.
+//!
+//! With the `serde` feature, every frame, segment and IPC struct implements `Serialize` and
+//! `Deserialize`. For the ones declared elsewhere with [`ipc_struct`], the crate declaring them
+//! needs a `serde` feature of its own that turns this one on.
+
+pub mod compression;
+pub mod encryption;
+pub mod fixed_str;
+pub mod ipc;
+pub mod opcodes;
+pub mod packets;
+
+pub use sapphire_protocol_derive::ipc_struct;
+
+// for the serde derives ipc_struct emits, so the crates using it don't need their own serde
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub use serde;
@@ -0,0 +1,109 @@
+//! Opcode tables, which say what each IPC's `ipc_type` is in a given game version.
+//!
+//! Opcodes move around between patches, so rather than being compiled in they're read from a
+//! TOML file with a table per game version and direction, mapping the names IPCs are declared
+//! with (see [`IpcPacket::NAME`]) to their opcodes:
+//!
+//! ```toml
+//! [6100.client_to_server]
+//! ClientVersionInfo = 0x0005
+//!
+//! [6100.server_to_client]
+//! ServiceAccountList = 0x000c
+//! ```
+
+use serde::Deserialize;
+use std::{collections::HashMap, error::Error, fs};
+
+use crate::ipc::{Direction, IpcPacket};
+
+/// The opcodes of one game version.
+#[derive(Deserialize, Clone, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OpcodeTable {
+    pub client_to_server: HashMap<String, u16>,
+    pub server_to_client: HashMap<String, u16>,
+}
+
+impl OpcodeTable {
+    fn direction(&self, direction: Direction) -> &HashMap<String, u16> {
+        match direction {
+            Direction::ClientToServer => &self.client_to_server,
+            Direction::ServerToClient => &self.server_to_client,
+        }
+    }
+
+    /// The opcode of the IPC called `name`.
+    pub fn opcode(&self, direction: Direction, name: &str) -> Option<u16> {
+        self.direction(direction).get(name).copied()
+    }
+
+    /// The opcode of an IPC struct.
+    pub fn opcode_of<T: IpcPacket>(&self) -> Option<u16> {
+        self.opcode(T::DIRECTION, T::NAME)
+    }
+
+    /// The name of the IPC with this opcode.
+    pub fn name(&self, direction: Direction, opcode: u16) -> Option<&str> {
+        self.direction(direction)
+            .iter()
+            .find(|(_, &o)| o == opcode)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+/// Every game version's [`OpcodeTable`].
+#[derive(Clone, Default, Debug)]
+pub struct OpcodeTables {
+    tables: HashMap<u16, OpcodeTable>,
+}
+
+impl OpcodeTables {
+    /// Reads tables from the TOML described in the [module docs](self).
+    ///
+    /// Two names sharing an opcode in the same direction is an error, as there'd be no telling
+    /// which one a client meant.
+    pub fn parse(toml: &str) -> Result<OpcodeTables, Box<dyn Error>> {
+        let raw: HashMap<String, OpcodeTable> = toml::from_str(toml)?;
+
+        let mut tables = HashMap::new();
+        for (game_version, table) in raw {
+            let game_version: u16 = game_version
+                .parse()
+                .map_err(|_| format!("{:?} isn't a game version", game_version))?;
+
+            for (direction, opcodes) in [
+                ("client_to_server", &table.client_to_server),
+                ("server_to_client", &table.server_to_client),
+            ] {
+                let mut seen: HashMap<u16, &str> = HashMap::new();
+                for (name, &opcode) in opcodes {
+                    if let Some(other) = seen.insert(opcode, name) {
+                        return Err(format!(
+                            "{} and {} are both {:#06x} in {}.{}",
+                            other, name, opcode, game_version, direction
+                        )
+                        .into());
+                    }
+                }
+            }
+
+            tables.insert(game_version, table);
+        }
+
+        Ok(OpcodeTables { tables })
+    }
+
+    /// [`parse`](Self::parse)s a file.
+    pub fn load(path: &str) -> Result<OpcodeTables, Box<dyn Error>> {
+        fs::read_to_string(path)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|toml| OpcodeTables::parse(&toml))
+            .map_err(|e| format!("couldn't read opcodes from {}: {}", path, e).into())
+    }
+
+    /// The table for a game version, if there is one.
+    pub fn get(&self, game_version: u16) -> Option<&OpcodeTable> {
+        self.tables.get(&game_version)
+    }
+}
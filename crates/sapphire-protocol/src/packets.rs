@@ -0,0 +1,263 @@
+//! Frames and the segments inside them.
+//!
+//! A frame is a [`PacketHeader`] followed by `count` segments, each a [`PacketSegmentHeader`] and
+//! its data. Frames arrive back to back on a connection, [`frame_size`] says where one ends and
+//! [`parse_frame`] takes it apart. [`write_frame`] puts one together.
+
+use binrw::{BinRead, BinWrite};
+use num_enum::TryFromPrimitive;
+use std::{
+    error::Error,
+    io::{Cursor, Write},
+    mem::size_of,
+};
+
+use crate::compression::{decompress, CompressionType};
+
+/// The 40 bytes at the start of every frame.
+#[derive(BinRead, BinWrite, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketHeader {
+    pub unknown_0: u64,
+    pub unknown_8: u64,
+
+    /// Milliseconds since the unix epoch.
+    pub timestamp: u64,
+    /// The whole frame, header included.
+    pub size: u32,
+    pub connection_type: u16,
+    pub count: u16,
+
+    pub unknown_20: u8,
+    /// A [`CompressionType`].
+    pub is_compressed: u8,
+    pub unknown_24: u16,
+    /// The size of the segments once decompressed, unused when the frame isn't compressed.
+    pub uncompressed_size: u32,
+}
+
+/// What a segment holds, the `segment_type` of its header.
+#[derive(BinRead, BinWrite, Clone, Copy, PartialEq, Eq, Debug, TryFromPrimitive)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[br(repr = u16)]
+#[bw(repr = u16)]
+#[repr(u16)]
+pub enum SegmentType {
+    SessionInit = 1,
+    /// An IPC, which starts with an [`IPCHeader`](crate::ipc::IPCHeader).
+    Ipc = 3,
+    KeepAlive = 7,
+    KeepAliveResponse = 8,
+    EncryptionInit = 9,
+}
+
+/// The body of both keepalive segments, the response echoes the request's.
+#[derive(BinRead, BinWrite, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct KeepAlive {
+    pub id: u32,
+    /// Milliseconds, on whatever clock the sender likes.
+    pub timestamp: u32,
+}
+
+/// The 16 bytes at the start of every segment.
+#[derive(BinRead, BinWrite, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketSegmentHeader {
+    /// The whole segment, header included.
+    pub size: u32,
+    pub source_actor: u32,
+    pub target_actor: u32,
+    /// A [`SegmentType`].
+    pub segment_type: u16,
+    pub padding: u16,
+}
+
+impl PacketSegmentHeader {
+    /// A header for a segment with `size` bytes of data.
+    pub fn new(
+        segment_type: u16,
+        size: u32,
+        source_actor: u32,
+        target_actor: u32,
+    ) -> PacketSegmentHeader {
+        PacketSegmentHeader {
+            size: (size_of::<PacketSegmentHeader>() as u32) + size,
+            source_actor,
+            target_actor,
+            segment_type,
+            padding: 0,
+        }
+    }
+}
+
+/// A segment taken out of a frame, its data still encrypted if it was sent that way.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct PacketRaw {
+    pub segment_header: PacketSegmentHeader,
+    pub data: Vec<u8>,
+}
+
+/// How much the other end is allowed to send, anything over is an error.
+#[derive(Clone, Copy, Debug)]
+pub struct FrameLimits {
+    /// The biggest frame, header included. Also the most a compressed frame's segments may
+    /// decompress to.
+    pub max_frame_size: usize,
+    pub max_segments: u16,
+    /// The biggest IPC, IPC header included.
+    pub max_ipc_size: usize,
+}
+
+impl Default for FrameLimits {
+    fn default() -> Self {
+        FrameLimits {
+            max_frame_size: 0x10000,
+            max_segments: 64,
+            max_ipc_size: 0x2000,
+        }
+    }
+}
+
+/// The size of the frame at the start of `buf`, `None` until enough of it has arrived to tell.
+///
+/// Errors if the frame claims to be smaller than its header or bigger than the limits allow,
+/// after which there's no finding where the next frame starts.
+pub fn frame_size(buf: &[u8], limits: &FrameLimits) -> Result<Option<usize>, Box<dyn Error>> {
+    if buf.len() < size_of::<PacketHeader>() {
+        return Ok(None);
+    }
+
+    let size = PacketHeader::read(&mut Cursor::new(buf))?.size as usize;
+    if size < size_of::<PacketHeader>() {
+        return Err(format!("frame claims {} bytes, less than its own header", size).into());
+    }
+    if size > limits.max_frame_size {
+        return Err(format!(
+            "frame claims {} bytes, more than the {} allowed",
+            size, limits.max_frame_size
+        )
+        .into());
+    }
+
+    Ok(Some(size))
+}
+
+/// Splits the frame at the start of `buf` into its segments, decompressing them first if need be.
+///
+/// Every size in a frame comes from the other end, so they're checked against the limits and
+/// the bytes actually there instead of being used to allocate.
+pub fn parse_frame(
+    buf: &[u8],
+    limits: &FrameLimits,
+) -> Result<(PacketHeader, Vec<PacketRaw>), Box<dyn Error>> {
+    let size = frame_size(buf, limits)?.ok_or("frame shorter than its header")?;
+    let buf = buf
+        .get(0..size)
+        .ok_or_else(|| format!("frame claims {} bytes but only {} arrived", size, buf.len()))?;
+
+    let header = PacketHeader::read(&mut Cursor::new(buf))?;
+    if header.count > limits.max_segments {
+        return Err(format!(
+            "frame claims {} segments, more than the {} allowed",
+            header.count, limits.max_segments
+        )
+        .into());
+    }
+
+    let body = &buf[size_of::<PacketHeader>()..];
+    let body = match CompressionType::try_from(header.is_compressed) {
+        Ok(CompressionType::None) => body.to_vec(),
+        Ok(compression) => decompress(
+            compression,
+            body,
+            header.uncompressed_size as usize,
+            limits.max_frame_size,
+        )?,
+        Err(_) => return Err(format!("unknown compression type {}", header.is_compressed).into()),
+    };
+
+    let mut cursor = Cursor::new(&body[..]);
+    let mut segments = Vec::new();
+    for i in 0..header.count {
+        let segment_header = PacketSegmentHeader::read(&mut cursor)?;
+
+        let data_size = (segment_header.size as usize)
+            .checked_sub(size_of::<PacketSegmentHeader>())
+            .ok_or_else(|| {
+                format!(
+                    "segment {} claims {} bytes, less than its own header",
+                    i, segment_header.size
+                )
+            })?;
+        if segment_header.segment_type == SegmentType::Ipc as u16 && data_size > limits.max_ipc_size
+        {
+            return Err(format!(
+                "segment {} claims a {} byte IPC, more than the {} allowed",
+                i, data_size, limits.max_ipc_size
+            )
+            .into());
+        }
+
+        let start = cursor.position() as usize;
+        let data = body[start..].get(0..data_size).ok_or_else(|| {
+            format!(
+                "segment {} claims {} bytes but the frame only has {} left",
+                i,
+                segment_header.size,
+                body.len() - start + size_of::<PacketSegmentHeader>()
+            )
+        })?;
+        cursor.set_position((start + data_size) as u64);
+
+        segments.push(PacketRaw {
+            segment_header,
+            data: data.to_vec(),
+        });
+    }
+
+    Ok((header, segments))
+}
+
+/// Puts an uncompressed frame together from its segments.
+///
+/// `encode` is given each segment before it's written and returns the data to send in its place,
+/// which is where IPCs get encrypted. It has to return as many bytes as it was given, the segment
+/// headers aren't touched.
+pub fn write_frame(
+    timestamp: u64,
+    segments: &[(PacketSegmentHeader, &[u8])],
+    mut encode: impl FnMut(&PacketSegmentHeader, &[u8]) -> Vec<u8>,
+) -> Vec<u8> {
+    let mut body = Cursor::new(Vec::new());
+    for (segment_header, data) in segments {
+        segment_header
+            .write_to(&mut body)
+            .expect("could not write packet segment header");
+        body.write_all(&encode(segment_header, data))
+            .expect("could not write packet data");
+    }
+    let body = body.into_inner();
+
+    let packet_header = PacketHeader {
+        unknown_0: 0,
+        unknown_8: 0,
+
+        timestamp,
+        size: (size_of::<PacketHeader>() + body.len()) as u32,
+        connection_type: 0,
+        count: segments.len() as u16,
+
+        unknown_20: 1,
+        is_compressed: CompressionType::None as u8,
+        unknown_24: 0,
+        uncompressed_size: 0,
+    };
+
+    let mut frame = Cursor::new(Vec::new());
+    packet_header
+        .write_to(&mut frame)
+        .expect("could not write packet header");
+    frame.write_all(&body).expect("could not write packet data");
+    frame.into_inner()
+}
@@ -0,0 +1,61 @@
+use binrw::{BinRead, BinWrite};
+use sapphire_protocol::fixed_str::{FixedStr, TooLong};
+use std::io::Cursor;
+
+fn write<const N: usize>(s: &FixedStr<N>) -> Vec<u8> {
+    let mut writer = Cursor::new(Vec::new());
+    s.write_to(&mut writer).unwrap();
+    writer.into_inner()
+}
+
+#[test]
+fn null_padded_on_the_wire() {
+    let s = FixedStr::<8>::new("abc").unwrap();
+    assert_eq!(write(&s), b"abc\0\0\0\0\0");
+    assert_eq!(s, "abc");
+    assert_eq!(s.as_str(), Ok("abc"));
+    assert!(!s.is_empty());
+    assert!(FixedStr::<8>::default().is_empty());
+}
+
+#[test]
+fn long_strings_are_refused_or_truncated() {
+    assert_eq!(
+        FixedStr::<6>::new("far too long"),
+        Err(TooLong {
+            len: 12,
+            capacity: 5
+        })
+    );
+    assert_eq!(FixedStr::<6>::new("fits!").unwrap(), "fits!");
+
+    let s = FixedStr::<6>::truncated("far too long");
+    assert_eq!(write(&s), b"far t\0");
+
+    // a character that doesn't fit is dropped whole rather than split
+    let s = FixedStr::<6>::truncated("abcdé");
+    assert_eq!(s, "abcd");
+    assert_eq!(write(&s), b"abcd\0\0");
+}
+
+#[test]
+fn reads_what_the_client_sent() {
+    let bytes = *b"abc\0junk";
+    let s = FixedStr::<8>::read(&mut Cursor::new(&bytes)).unwrap();
+    assert_eq!(s, "abc");
+    // whatever came after the null goes back out as it came
+    assert_eq!(write(&s), bytes);
+
+    // so does a buffer without any null
+    let s = FixedStr::<4>::read(&mut Cursor::new(b"abcd")).unwrap();
+    assert_eq!(s, "abcd");
+}
+
+#[test]
+fn invalid_utf8_is_refused_or_replaced() {
+    let s = FixedStr::from(*b"a\xffb\0");
+    assert!(s.as_str().is_err());
+    assert_eq!(s.to_string_lossy(), "a\u{fffd}b");
+    assert_eq!(format!("{:?}", s), "\"a\u{fffd}b\"");
+    assert_eq!(s.to_string(), "a\u{fffd}b");
+}
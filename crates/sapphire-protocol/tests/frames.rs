@@ -0,0 +1,159 @@
+use sapphire_protocol::{
+    compression::{compress, CompressionType},
+    encryption::{decrypt_ipc, derive_key, encrypt_ipc},
+    ipc::Direction,
+    packets::{parse_frame, write_frame, FrameLimits, PacketSegmentHeader, SegmentType},
+};
+
+fn segments() -> Vec<(u16, Vec<u8>)> {
+    vec![
+        (SegmentType::KeepAlive as u16, vec![1, 2, 3, 4, 5, 6, 7, 8]),
+        (SegmentType::Ipc as u16, (0..0x1b).collect()),
+    ]
+}
+
+fn frame(encode: impl FnMut(&PacketSegmentHeader, &[u8]) -> Vec<u8>) -> Vec<u8> {
+    let segments = segments();
+    let segments: Vec<(PacketSegmentHeader, &[u8])> = segments
+        .iter()
+        .map(|(segment_type, data)| {
+            let header = PacketSegmentHeader::new(*segment_type, data.len() as u32, 1, 2);
+            (header, &data[..])
+        })
+        .collect();
+    write_frame(1234, &segments, encode)
+}
+
+// the same segments, compressed as a whole
+fn compressed_frame(compression: CompressionType) -> Vec<u8> {
+    let frame = frame(|_, data| data.to_vec());
+    let body = &frame[40..];
+    let compressed = compress(CompressionType::Zlib, body).unwrap();
+
+    let mut out = frame[0..40].to_vec();
+    out[24..28].copy_from_slice(&((40 + compressed.len()) as u32).to_le_bytes());
+    out[33] = compression as u8;
+    out[36..40].copy_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&compressed);
+    out
+}
+
+#[test]
+fn written_frames_parse_back() {
+    let frame = frame(|_, data| data.to_vec());
+
+    let (header, parsed) = parse_frame(&frame, &FrameLimits::default()).unwrap();
+    assert_eq!(header.timestamp, 1234);
+    assert_eq!(header.size as usize, frame.len());
+    assert_eq!(header.count, 2);
+    for ((segment_type, data), segment) in segments().iter().zip(parsed) {
+        assert_eq!(segment.segment_header.segment_type, *segment_type);
+        assert_eq!(segment.segment_header.source_actor, 1);
+        assert_eq!(segment.segment_header.target_actor, 2);
+        assert_eq!(&segment.data, data);
+    }
+}
+
+#[test]
+fn write_frame_encodes_each_segment() {
+    let key = derive_key(&[1, 2, 3, 4], &[b'a'; 32], 6100);
+    let frame = frame(
+        |header, data| match header.segment_type == SegmentType::Ipc as u16 {
+            true => encrypt_ipc(&key, Direction::ClientToServer, data),
+            false => data.to_vec(),
+        },
+    );
+    let (_, parsed) = parse_frame(&frame, &FrameLimits::default()).unwrap();
+
+    let segments = segments();
+    assert_eq!(parsed[0].data, segments[0].1);
+    assert_ne!(parsed[1].data, segments[1].1);
+    assert_eq!(
+        decrypt_ipc(&key, Direction::ClientToServer, &parsed[1].data),
+        segments[1].1
+    );
+}
+
+#[test]
+fn clients_leave_their_last_bytes_plain() {
+    let key = derive_key(&[1, 2, 3, 4], &[b'a'; 32], 6100);
+    let data: Vec<u8> = (1..=0x1b).collect();
+
+    // 0x13 bytes before the last 8, so two whole blocks
+    let encrypted = encrypt_ipc(&key, Direction::ClientToServer, &data);
+    assert_eq!(encrypted.len(), data.len());
+    assert_ne!(encrypted[0..0x10], data[0..0x10]);
+    assert_eq!(encrypted[0x10..], data[0x10..]);
+    assert_eq!(
+        decrypt_ipc(&key, Direction::ClientToServer, &encrypted),
+        data
+    );
+}
+
+#[test]
+fn servers_zero_their_last_bytes() {
+    let key = derive_key(&[1, 2, 3, 4], &[b'a'; 32], 6100);
+    let data: Vec<u8> = (1..=0x1b).collect();
+
+    // 0xb bytes before the last 0x10, padded to two blocks
+    let encrypted = encrypt_ipc(&key, Direction::ServerToClient, &data);
+    assert_eq!(encrypted.len(), data.len());
+    assert_ne!(encrypted[0..0x10], data[0..0x10]);
+    assert_eq!(encrypted[0x10..], [0; 0xb]);
+
+    let decrypted = decrypt_ipc(&key, Direction::ServerToClient, &encrypted);
+    assert_eq!(decrypted[0..0xb], data[0..0xb]);
+    assert_eq!(decrypted[0xb..], [0; 0x10]);
+}
+
+#[test]
+fn keys_differ_by_game_version() {
+    let phrase = [b'a'; 32];
+    assert_eq!(derive_key(&[1, 2, 3, 4], &phrase, 6100).len(), 16);
+    assert_ne!(
+        derive_key(&[1, 2, 3, 4], &phrase, 6100),
+        derive_key(&[1, 2, 3, 4], &phrase, 6200)
+    );
+}
+
+#[test]
+fn zlib_frames_are_decompressed() {
+    let (header, parsed) = parse_frame(
+        &compressed_frame(CompressionType::Zlib),
+        &FrameLimits::default(),
+    )
+    .unwrap();
+    assert_eq!(header.count, 2);
+    for ((_, data), segment) in segments().iter().zip(parsed) {
+        assert_eq!(&segment.data, data);
+    }
+}
+
+#[test]
+fn decompression_is_held_to_the_frame_size_limit() {
+    let limits = FrameLimits {
+        max_frame_size: 0x40,
+        ..FrameLimits::default()
+    };
+    assert!(parse_frame(&compressed_frame(CompressionType::Zlib), &limits).is_err());
+}
+
+#[test]
+fn uncompressed_size_has_to_match() {
+    let mut frame = compressed_frame(CompressionType::Zlib);
+    frame[36] += 1;
+    assert!(parse_frame(&frame, &FrameLimits::default()).is_err());
+}
+
+#[test]
+fn oodle_and_unknown_compression_are_refused() {
+    assert!(parse_frame(
+        &compressed_frame(CompressionType::Oodle),
+        &FrameLimits::default()
+    )
+    .is_err());
+
+    let mut frame = compressed_frame(CompressionType::Zlib);
+    frame[33] = 7;
+    assert!(parse_frame(&frame, &FrameLimits::default()).is_err());
+}
@@ -0,0 +1,113 @@
+// hostile frames, most of them found by the lobby's fuzz targets
+use sapphire_protocol::{
+    encryption::encryption_init_key,
+    ipc::split_ipc,
+    packets::{frame_size, parse_frame, FrameLimits, SegmentType},
+};
+
+// a frame whose segment headers say whatever we like, regardless of the data that follows
+fn frame(count: u16, segments: &[(u16, u32, &[u8])]) -> Vec<u8> {
+    let data_len: usize = segments.iter().map(|(_, _, data)| 16 + data.len()).sum();
+
+    let mut frame = vec![0; 40];
+    frame[24..28].copy_from_slice(&((40 + data_len) as u32).to_le_bytes());
+    frame[30..32].copy_from_slice(&count.to_le_bytes());
+
+    for (segment_type, size, data) in segments {
+        frame.extend_from_slice(&size.to_le_bytes());
+        frame.extend_from_slice(&[0; 8]);
+        frame.extend_from_slice(&segment_type.to_le_bytes());
+        frame.extend_from_slice(&[0; 2]);
+        frame.extend_from_slice(data);
+    }
+    frame
+}
+
+#[test]
+fn segment_smaller_than_its_header() {
+    let buf = frame(1, &[(SegmentType::Ipc as u16, 4, &[0; 8])]);
+    assert!(parse_frame(&buf, &FrameLimits::default()).is_err());
+}
+
+#[test]
+fn segment_claiming_more_than_the_frame_holds() {
+    let buf = frame(1, &[(SegmentType::Ipc as u16, u32::MAX, &[0; 8])]);
+    assert!(parse_frame(&buf, &FrameLimits::default()).is_err());
+}
+
+#[test]
+fn more_segments_than_the_frame_holds() {
+    let buf = frame(2, &[(SegmentType::KeepAlive as u16, 24, &[0; 8])]);
+    assert!(parse_frame(&buf, &FrameLimits::default()).is_err());
+}
+
+#[test]
+fn segment_running_past_the_frame_size() {
+    // the bytes are there, but past where the frame header says the frame ends
+    let mut buf = frame(1, &[(SegmentType::KeepAlive as u16, 24, &[0; 4])]);
+    buf.extend_from_slice(&[0; 4]);
+    assert!(parse_frame(&buf, &FrameLimits::default()).is_err());
+}
+
+#[test]
+fn frame_size_limit() {
+    let limits = FrameLimits {
+        max_frame_size: 64,
+        ..FrameLimits::default()
+    };
+    let buf = frame(1, &[(SegmentType::KeepAlive as u16, 24, &[0; 8])]);
+    assert_eq!(frame_size(&buf, &limits).unwrap(), Some(64));
+    assert_eq!(frame_size(&buf[0..39], &limits).unwrap(), None);
+
+    let buf = frame(1, &[(SegmentType::KeepAlive as u16, 25, &[0; 9])]);
+    assert!(frame_size(&buf, &limits).is_err());
+    assert!(parse_frame(&buf, &limits).is_err());
+}
+
+#[test]
+fn segment_count_limit() {
+    let limits = FrameLimits {
+        max_segments: 1,
+        ..FrameLimits::default()
+    };
+    let keepalive = (SegmentType::KeepAlive as u16, 24, &[0; 8][..]);
+    assert!(parse_frame(&frame(1, &[keepalive]), &limits).is_ok());
+    assert!(parse_frame(&frame(2, &[keepalive, keepalive]), &limits).is_err());
+}
+
+#[test]
+fn ipc_size_limit() {
+    let limits = FrameLimits {
+        max_ipc_size: 16,
+        ..FrameLimits::default()
+    };
+    let ipc = frame(1, &[(SegmentType::Ipc as u16, 32, &[0; 16])]);
+    assert!(parse_frame(&ipc, &limits).is_ok());
+    let ipc = frame(1, &[(SegmentType::Ipc as u16, 40, &[0; 24])]);
+    assert!(parse_frame(&ipc, &limits).is_err());
+
+    // only IPCs are held to it
+    let init = frame(1, &[(SegmentType::EncryptionInit as u16, 40, &[0; 24])]);
+    assert!(parse_frame(&init, &limits).is_ok());
+}
+
+#[test]
+fn frame_shorter_than_its_header() {
+    assert!(parse_frame(&[0; 12], &FrameLimits::default()).is_err());
+}
+
+#[test]
+fn short_encryption_init() {
+    assert!(encryption_init_key(&[0; 100]).is_none());
+    assert!(encryption_init_key(&[0; 104]).is_some());
+}
+
+#[test]
+fn ipc_shorter_than_its_header() {
+    assert!(split_ipc(&[]).is_none());
+    assert!(split_ipc(&[0; 3]).is_none());
+    assert_eq!(
+        split_ipc(&[0, 0, 5, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]),
+        Some((5, &[][..]))
+    );
+}
@@ -0,0 +1,69 @@
+use binrw::{BinRead, BinWrite};
+use sapphire_protocol::{
+    fixed_str::FixedStr,
+    ipc::{Direction, IpcPacket, WireSize},
+    ipc_struct,
+};
+use std::io::Cursor;
+
+#[ipc_struct(size = 0x0c)]
+#[derive(Clone, PartialEq, Debug)]
+struct Entry {
+    #[ipc(offset = 2)]
+    id: u16,
+    name: FixedStr<6>,
+}
+
+#[ipc_struct(name = "List", direction = server_to_client, size = 0x24)]
+#[derive(PartialEq, Debug)]
+struct List {
+    seq: u64,
+    len: u8,
+    flags: u8,
+    #[ipc(offset = 0x0c)]
+    entries: [Entry; 2],
+}
+
+fn write(list: &List) -> Vec<u8> {
+    let mut writer = Cursor::new(Vec::new());
+    list.write_to(&mut writer).unwrap();
+    writer.into_inner()
+}
+
+#[test]
+fn sizes_add_up() {
+    assert_eq!(Entry::SIZE, 0x0c);
+    assert_eq!(List::SIZE, 0x24);
+    assert_eq!(write(&List::default()).len(), List::SIZE);
+}
+
+#[test]
+fn packets_know_their_name() {
+    assert_eq!(List::NAME, "List");
+    assert_eq!(List::DIRECTION, Direction::ServerToClient);
+}
+
+#[test]
+fn padding_and_strings_on_the_wire() {
+    let entry = Entry {
+        id: 0x0201,
+        name: FixedStr::new("abc").unwrap(),
+    };
+    let list = List {
+        seq: 1,
+        len: 1,
+        flags: 0xff,
+        entries: [entry.clone(), Entry::default()],
+    };
+
+    let bytes = write(&list);
+    assert_eq!(&bytes[8..12], &[1, 0xff, 0, 0]);
+    assert_eq!(
+        &bytes[12..24],
+        &[0, 0, 1, 2, b'a', b'b', b'c', 0, 0, 0, 0, 0]
+    );
+    assert_eq!(&bytes[24..], &[0; 12]);
+
+    let read = List::read(&mut Cursor::new(&bytes)).unwrap();
+    assert_eq!(read, list);
+}
@@ -0,0 +1,59 @@
+use sapphire_protocol::{
+    ipc::{Direction, IpcPacket},
+    opcodes::OpcodeTables,
+};
+
+const TABLES: &str = "
+[6000.client_to_server]
+Hello = 0x0005
+
+[6000.server_to_client]
+Hello = 0x0005
+Goodbye = 0x0010
+
+[6100.client_to_server]
+Hello = 0x0105
+";
+
+struct Hello;
+
+impl IpcPacket for Hello {
+    const NAME: &'static str = "Hello";
+    const DIRECTION: Direction = Direction::ClientToServer;
+}
+
+#[test]
+fn looks_up_both_ways_per_version() {
+    let tables = OpcodeTables::parse(TABLES).unwrap();
+
+    let old = tables.get(6000).unwrap();
+    assert_eq!(old.opcode(Direction::ServerToClient, "Goodbye"), Some(0x10));
+    assert_eq!(old.name(Direction::ServerToClient, 0x10), Some("Goodbye"));
+    assert_eq!(old.name(Direction::ClientToServer, 0x10), None);
+    assert_eq!(old.opcode_of::<Hello>(), Some(0x5));
+
+    let new = tables.get(6100).unwrap();
+    assert_eq!(new.opcode_of::<Hello>(), Some(0x105));
+    assert_eq!(new.name(Direction::ClientToServer, 0x5), None);
+    assert_eq!(new.opcode(Direction::ServerToClient, "Goodbye"), None);
+
+    assert!(tables.get(6200).is_none());
+}
+
+#[test]
+fn refuses_shared_opcodes() {
+    let err = OpcodeTables::parse("[6100.client_to_server]\nHello = 1\nGoodbye = 1\n").unwrap_err();
+    assert!(err.to_string().contains("0x0001"), "{}", err);
+
+    // the same opcode in each direction is fine
+    OpcodeTables::parse("[6100.client_to_server]\nHello = 1\n[6100.server_to_client]\nHello = 1\n")
+        .unwrap();
+}
+
+#[test]
+fn refuses_bad_tables() {
+    assert!(OpcodeTables::parse("[patch.client_to_server]\nHello = 1\n").is_err());
+    assert!(OpcodeTables::parse("[70000.client_to_server]\nHello = 1\n").is_err());
+    assert!(OpcodeTables::parse("[6100.sideways]\nHello = 1\n").is_err());
+    assert!(OpcodeTables::parse("[6100.client_to_server]\nHello = 0x10000\n").is_err());
+}
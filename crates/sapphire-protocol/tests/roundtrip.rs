@@ -0,0 +1,140 @@
+use binrw::{BinRead, BinWrite};
+use proptest::{collection::vec, prelude::*};
+use sapphire_protocol::{
+    fixed_str::FixedStr,
+    ipc::IPCHeader,
+    packets::{KeepAlive, PacketHeader, PacketSegmentHeader, SegmentType},
+};
+use std::io::Cursor;
+
+// writes a value, checks it came to `size` bytes and that reading them back gives the same value
+macro_rules! assert_roundtrip {
+    ($ty:ty, $value:expr, $size:expr) => {{
+        let value: $ty = $value;
+        let mut writer = Cursor::new(Vec::new());
+        value.write_to(&mut writer).unwrap();
+        let bytes = writer.into_inner();
+        prop_assert_eq!(bytes.len(), $size);
+
+        let mut reader = Cursor::new(&bytes);
+        let read = <$ty>::read(&mut reader).unwrap();
+        prop_assert_eq!(reader.position() as usize, $size);
+        prop_assert_eq!(read, value);
+    }};
+}
+
+fn packet_header() -> impl Strategy<Value = PacketHeader> {
+    (
+        (any::<u64>(), any::<u64>(), any::<u64>()),
+        (any::<u32>(), any::<u16>(), any::<u16>()),
+        (any::<u8>(), any::<u8>(), any::<u16>(), any::<u32>()),
+    )
+        .prop_map(
+            |(
+                (unknown_0, unknown_8, timestamp),
+                (size, connection_type, count),
+                (unknown_20, is_compressed, unknown_24, uncompressed_size),
+            )| PacketHeader {
+                unknown_0,
+                unknown_8,
+                timestamp,
+                size,
+                connection_type,
+                count,
+                unknown_20,
+                is_compressed,
+                unknown_24,
+                uncompressed_size,
+            },
+        )
+}
+
+fn segment_header() -> impl Strategy<Value = PacketSegmentHeader> {
+    (
+        any::<u32>(),
+        any::<u32>(),
+        any::<u32>(),
+        any::<u16>(),
+        any::<u16>(),
+    )
+        .prop_map(
+            |(size, source_actor, target_actor, segment_type, padding)| PacketSegmentHeader {
+                size,
+                source_actor,
+                target_actor,
+                segment_type,
+                padding,
+            },
+        )
+}
+
+fn segment_type() -> impl Strategy<Value = SegmentType> {
+    prop_oneof![
+        Just(SegmentType::SessionInit),
+        Just(SegmentType::Ipc),
+        Just(SegmentType::KeepAlive),
+        Just(SegmentType::KeepAliveResponse),
+        Just(SegmentType::EncryptionInit),
+    ]
+}
+
+fn ipc_header() -> impl Strategy<Value = IPCHeader> {
+    (
+        any::<u16>(),
+        any::<u16>(),
+        any::<u16>(),
+        any::<u16>(),
+        any::<u32>(),
+        any::<u32>(),
+    )
+        .prop_map(
+            |(reserved, ipc_type, padding, server_id, timestamp, padding1)| IPCHeader {
+                reserved,
+                ipc_type,
+                padding,
+                server_id,
+                timestamp,
+                padding1,
+            },
+        )
+}
+
+proptest! {
+    #[test]
+    fn packet_headers(header in packet_header()) {
+        assert_roundtrip!(PacketHeader, header, 40);
+    }
+
+    #[test]
+    fn segment_headers(header in segment_header()) {
+        assert_roundtrip!(PacketSegmentHeader, header, 16);
+    }
+
+    #[test]
+    fn segment_types(segment_type in segment_type()) {
+        assert_roundtrip!(SegmentType, segment_type, 2);
+    }
+
+    #[test]
+    fn keepalives(id in any::<u32>(), timestamp in any::<u32>()) {
+        assert_roundtrip!(KeepAlive, KeepAlive { id, timestamp }, 8);
+    }
+
+    #[test]
+    fn ipc_headers(header in ipc_header()) {
+        assert_roundtrip!(IPCHeader, header, 16);
+    }
+
+    #[test]
+    fn strings(s in ".{0,40}") {
+        let fixed = FixedStr::<0x20>::truncated(&s);
+        prop_assert!(s.starts_with(fixed.as_str().unwrap()));
+        assert_roundtrip!(FixedStr<0x20>, fixed, 0x20);
+    }
+
+    #[test]
+    fn strings_with_any_bytes(bytes in vec(any::<u8>(), 0x20)) {
+        let fixed = FixedStr::<0x20>::from(<[u8; 0x20]>::try_from(bytes).unwrap());
+        assert_roundtrip!(FixedStr<0x20>, fixed, 0x20);
+    }
+}
@@ -0,0 +1,114 @@
+use binrw::{BinRead, BinWrite};
+use sapphire_protocol::{
+    fixed_str::FixedStr,
+    ipc_struct,
+    packets::{PacketSegmentHeader, SegmentType},
+};
+use serde_json::json;
+use std::io::Cursor;
+
+#[ipc_struct(size = 0x0c)]
+#[derive(PartialEq, Debug)]
+struct Entry {
+    #[ipc(offset = 2)]
+    id: u16,
+    name: FixedStr<6>,
+}
+
+#[ipc_struct(name = "List", direction = server_to_client, size = 0x24)]
+#[derive(PartialEq, Debug)]
+struct List {
+    seq: u64,
+    len: u8,
+    flags: u8,
+    #[ipc(offset = 0x0c)]
+    entries: [Entry; 2],
+}
+
+#[test]
+fn ipc_structs_from_json_fixtures() {
+    let fixture = json!({
+        "seq": 1,
+        "len": 1,
+        "flags": 255,
+        "entries": [
+            { "id": 0x0201, "name": "abc" },
+            { "id": 0, "name": "" },
+        ],
+    });
+
+    let list: List = serde_json::from_value(fixture.clone()).unwrap();
+    let mut writer = Cursor::new(Vec::new());
+    list.write_to(&mut writer).unwrap();
+    let bytes = writer.into_inner();
+    assert_eq!(
+        &bytes[8..24],
+        &[1, 0xff, 0, 0, 0, 0, 1, 2, b'a', b'b', b'c', 0, 0, 0, 0, 0]
+    );
+
+    let read = List::read(&mut Cursor::new(&bytes)).unwrap();
+    assert_eq!(serde_json::to_value(&read).unwrap(), fixture);
+}
+
+#[test]
+fn strings_that_would_lose_bytes_are_arrays() {
+    let junk = FixedStr::from(*b"ab\0c");
+    assert_eq!(serde_json::to_value(&junk).unwrap(), json!([97, 98, 0, 99]));
+    assert_eq!(
+        serde_json::from_value::<FixedStr<4>>(json!([97, 98, 0, 99])).unwrap(),
+        junk
+    );
+
+    let not_utf8 = FixedStr::from(*b"\xff\0\0\0");
+    assert_eq!(
+        serde_json::to_value(&not_utf8).unwrap(),
+        json!([255, 0, 0, 0])
+    );
+
+    assert!(serde_json::from_value::<FixedStr<4>>(json!("abcd")).is_err());
+    assert!(serde_json::from_value::<FixedStr<4>>(json!([1, 2, 3])).is_err());
+    assert!(serde_json::from_value::<FixedStr<4>>(json!([1, 2, 3, 4, 5])).is_err());
+}
+
+#[test]
+fn segment_headers() {
+    let header = PacketSegmentHeader::new(SegmentType::Ipc as u16, 0x20, 1, 2);
+    assert_eq!(
+        serde_json::to_value(&header).unwrap(),
+        json!({
+            "size": 0x30,
+            "source_actor": 1,
+            "target_actor": 2,
+            "segment_type": 3,
+            "padding": 0,
+        })
+    );
+    assert_eq!(
+        serde_json::to_value(SegmentType::KeepAlive).unwrap(),
+        json!("KeepAlive")
+    );
+}
+
+#[test]
+fn binary_formats_get_the_bytes() {
+    let name = FixedStr::<6>::new("abc").unwrap();
+    let bytes = bincode::serialize(&name).unwrap();
+    // bincode puts the length in front
+    assert_eq!(&bytes[8..], b"abc\0\0\0");
+    assert_eq!(bincode::deserialize::<FixedStr<6>>(&bytes).unwrap(), name);
+    assert!(bincode::deserialize::<FixedStr<4>>(&bytes).is_err());
+
+    let list = List {
+        seq: 1,
+        entries: [
+            Entry {
+                id: 2,
+                name: FixedStr::from(*b"ab\0cd\0"),
+            },
+            Entry::default(),
+        ],
+        ..Default::default()
+    };
+    let bytes = bincode::serialize(&list).unwrap();
+    assert_eq!(bincode::deserialize::<List>(&bytes).unwrap(), list);
+}
@@ -0,0 +1,199 @@
+mod migrations;
+mod sqlite;
+
+use std::{error::Error, fmt};
+
+pub use sqlite::SqliteStorage;
+
+#[derive(Debug)]
+pub enum StorageError {
+    Sqlite(rusqlite::Error),
+    // the database has been migrated by a newer build than this one
+    SchemaTooNew { found: u32, supported: u32 },
+    // a sequence ran into the next range of ids
+    IdsExhausted(&'static str),
+    // longer than MAX_DETAIL_JSON_LEN bytes
+    DetailJsonTooLong(usize),
+    // characters from before names were unique that have to be renamed or deleted before the
+    // database can be upgraded
+    DuplicateNames(Vec<String>),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StorageError::Sqlite(e) => write!(f, "sqlite error: {}", e),
+            StorageError::SchemaTooNew { found, supported } => write!(
+                f,
+                "database schema version {} is newer than the {} this build supports",
+                found, supported
+            ),
+            StorageError::IdsExhausted(kind) => write!(f, "ran out of {} ids", kind),
+            StorageError::DetailJsonTooLong(len) => write!(
+                f,
+                "a {} byte character description is over the {} byte limit",
+                len, MAX_DETAIL_JSON_LEN
+            ),
+            StorageError::DuplicateNames(duplicates) => write!(
+                f,
+                "names have to be unique on a world before the database can be upgraded, rename \
+                or delete: {}",
+                duplicates.join(", ")
+            ),
+        }
+    }
+}
+
+impl Error for StorageError {}
+
+impl From<rusqlite::Error> for StorageError {
+    fn from(e: rusqlite::Error) -> Self {
+        StorageError::Sqlite(e)
+    }
+}
+
+pub type Result<T> = std::result::Result<T, StorageError>;
+
+// ids are a base plus a sequence number, so they look like the ones the client sees on
+// retail: content ids around 0x0040000000000000 and player actor ids starting 0x10
+pub const CONTENT_ID_BASE: u64 = 0x0040_0000_0000_0000;
+pub const CHARACTER_ID_BASE: u32 = 0x1000_0000;
+// the lobby talks as an actor of its own before there's a character to talk as
+pub const LOBBY_ACTOR_ID_BASE: u32 = 0xe000_0000;
+
+// the character list has 1050 bytes for a character's JSON, null included, and a cut off one
+// is no use to the client
+pub const MAX_DETAIL_JSON_LEN: usize = 1049;
+
+pub struct Account {
+    pub id: u32,
+    pub username: String,
+}
+
+pub struct ServiceAccount {
+    pub id: u32,
+    pub name: String,
+    pub enabled: bool,
+}
+
+pub struct Character {
+    pub id: u32,
+    pub content_id: u64,
+    // also the character's actor id once it's in the world
+    pub character_id: u32,
+    pub world_id: u16,
+    pub name: String,
+    // the client's own JSON description of the character, sent back as-is in the list
+    pub detail_json: String,
+}
+
+pub struct Retainer {
+    pub id: u32,
+    // the content id of the character that employs them
+    pub owner_content_id: u64,
+    pub name: String,
+    pub class_job: u8,
+    pub level: u8,
+}
+
+pub struct QueueEntry {
+    pub ticket: u64,
+    pub world_id: u16,
+    pub content_id: u64,
+    pub joined_at: u64,
+}
+
+pub trait AccountRepository {
+    fn add_account(&self, username: &str) -> Result<u32>;
+    fn account(&self, id: u32) -> Result<Option<Account>>;
+}
+
+// sessions are handed out by the login server, we only ever look them up
+pub trait SessionRepository {
+    fn add_session(&self, account_id: u32, session_id: &str) -> Result<()>;
+    fn remove_session(&self, session_id: &str) -> Result<()>;
+    fn account_for_session(&self, session_id: &str) -> Result<Option<u32>>;
+}
+
+pub trait ServiceAccountRepository {
+    // new service accounts go to the end of the account's list
+    fn add_service_account(&self, account_id: u32, name: &str) -> Result<u32>;
+    // returns false if there's no service account with that id
+    fn set_service_account_enabled(&self, id: u32, enabled: bool) -> Result<bool>;
+    // in persisted order, which is also the order the lobby indexes them in
+    fn service_accounts(&self, account_id: u32) -> Result<Vec<ServiceAccount>>;
+}
+
+pub trait CharacterRepository {
+    fn add_character(&self, account_id: u32, world_id: u16, name: &str) -> Result<Character>;
+    fn characters(&self, account_id: u32) -> Result<Vec<Character>>;
+    fn character_by_content_id(&self, content_id: u64) -> Result<Option<Character>>;
+    // returns false if there's no character with that id
+    fn set_character_detail_json(&self, id: u32, detail_json: &str) -> Result<bool>;
+    // names are unique per world, ignoring case
+    fn character_name_taken(&self, world_id: u16, name: &str) -> Result<bool>;
+    fn delete_character(&self, id: u32) -> Result<()>;
+}
+
+pub trait RetainerRepository {
+    fn add_retainer(&self, character_id: u32, name: &str) -> Result<u32>;
+    // returns false if there's no retainer with that id
+    fn remove_retainer(&self, id: u32) -> Result<bool>;
+    fn retainer_count(&self, character_id: u32) -> Result<u32>;
+    // every retainer employed by any of the account's characters
+    fn retainers(&self, account_id: u32) -> Result<Vec<Retainer>>;
+}
+
+// the queue lives in storage so every lobby sharing it sees the same order, and so the
+// admin commands can look at it
+pub trait LoginQueueRepository {
+    fn join_queue(&self, world_id: u16, content_id: u64) -> Result<u64>;
+    // tickets nobody has asked about since stale_after seconds ago belong to clients that
+    // went away without leaving, so they're dropped before counting.
+    // None means the ticket is gone, either admitted elsewhere or cleared by an admin
+    fn queue_position(&self, ticket: u64, stale_after: u64) -> Result<Option<u32>>;
+    fn queue_len(&self, world_id: u16) -> Result<u32>;
+    fn leave_queue(&self, ticket: u64) -> Result<()>;
+    // admissions are kept so the wait can be estimated from how fast the queue really moves
+    fn record_admission(&self, world_id: u16) -> Result<()>;
+    // average seconds between the last few admissions, if there have been enough of them
+    fn admission_interval(&self, world_id: u16) -> Result<Option<u64>>;
+    fn queue(&self, world_id: Option<u16>) -> Result<Vec<QueueEntry>>;
+    fn clear_queue(&self, world_id: Option<u16>) -> Result<usize>;
+}
+
+// every server takes ids from here, so they stay unique and are never handed out twice no
+// matter how many lobbies share the database. the zone and chat servers look characters up
+// by content id rather than allocating, so a character has the same actor id everywhere
+pub trait IdAllocator {
+    fn allocate_content_id(&self) -> Result<u64>;
+    fn allocate_character_id(&self) -> Result<u32>;
+    fn allocate_lobby_actor_id(&self) -> Result<u32>;
+}
+
+// everything a server needs, so it can hold one Arc<dyn Storage>
+pub trait Storage:
+    AccountRepository
+    + SessionRepository
+    + ServiceAccountRepository
+    + CharacterRepository
+    + RetainerRepository
+    + LoginQueueRepository
+    + IdAllocator
+    + Send
+    + Sync
+{
+}
+
+impl<T> Storage for T where
+    T: AccountRepository
+        + SessionRepository
+        + ServiceAccountRepository
+        + CharacterRepository
+        + RetainerRepository
+        + LoginQueueRepository
+        + IdAllocator
+        + Send
+        + Sync
+{
+}
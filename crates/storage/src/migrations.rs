@@ -0,0 +1,146 @@
+use rusqlite::Connection;
+
+use crate::{Result, StorageError};
+
+// each entry moves the schema up one version, and PRAGMA user_version records how many have
+// run. never change one that has shipped, add another instead.
+// the first one uses IF NOT EXISTS so databases from before migrations pick up where they were
+const MIGRATIONS: &[&str] = &[
+    // 1: everything the lobby had before this crate existed
+    "CREATE TABLE IF NOT EXISTS accounts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        username TEXT NOT NULL UNIQUE
+    );
+    CREATE TABLE IF NOT EXISTS sessions (
+        session_id TEXT PRIMARY KEY,
+        account_id INTEGER NOT NULL REFERENCES accounts(id)
+    );
+    CREATE TABLE IF NOT EXISTS service_accounts (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        account_id INTEGER NOT NULL REFERENCES accounts(id),
+        name TEXT NOT NULL,
+        position INTEGER NOT NULL,
+        enabled INTEGER NOT NULL DEFAULT 1
+    );
+    CREATE TABLE IF NOT EXISTS characters (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        content_id INTEGER NOT NULL UNIQUE,
+        account_id INTEGER NOT NULL REFERENCES accounts(id),
+        world_id INTEGER NOT NULL,
+        name TEXT NOT NULL,
+        detail_json TEXT NOT NULL DEFAULT ''
+    );
+    CREATE TABLE IF NOT EXISTS retainers (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        character_id INTEGER NOT NULL REFERENCES characters(id),
+        name TEXT NOT NULL,
+        class_job INTEGER NOT NULL DEFAULT 0,
+        level INTEGER NOT NULL DEFAULT 1
+    );
+    CREATE TABLE IF NOT EXISTS login_queue (
+        ticket INTEGER PRIMARY KEY AUTOINCREMENT,
+        world_id INTEGER NOT NULL,
+        content_id INTEGER NOT NULL,
+        joined_at INTEGER NOT NULL,
+        last_seen INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS queue_admissions (
+        world_id INTEGER NOT NULL,
+        admitted_at INTEGER NOT NULL
+    );",
+    // 2: lookups the lobby does on every login
+    "CREATE INDEX service_accounts_account_id ON service_accounts(account_id);
+    CREATE INDEX characters_account_id ON characters(account_id);
+    CREATE INDEX retainers_character_id ON retainers(character_id);
+    CREATE INDEX login_queue_world_id ON login_queue(world_id, ticket);",
+    // 3: so two lobbies can't both hand out the same name on a world. older databases could
+    // already have some, which duplicate_names finds before this runs
+    "CREATE UNIQUE INDEX characters_world_name ON characters(world_id, name COLLATE NOCASE);",
+    // 4: ids handed out from sequences instead of row ids. characters from before keep their
+    // content ids, which are far below the new ones, and get character ids in the new range
+    "CREATE TABLE id_sequences (
+        name TEXT PRIMARY KEY,
+        last INTEGER NOT NULL
+    );
+    ALTER TABLE characters ADD COLUMN character_id INTEGER NOT NULL DEFAULT 0;
+    UPDATE characters SET character_id = 268435456 + id;
+    CREATE UNIQUE INDEX characters_character_id ON characters(character_id);
+    INSERT INTO id_sequences (name, last) VALUES ('content', 0), ('lobby_actor', 0);
+    INSERT INTO id_sequences (name, last) SELECT 'character', COALESCE(MAX(id), 0) FROM characters;",
+    // 5: character descriptions have to fit the character list, including ones written by
+    // something other than this crate. any that don't already are dropped, the client shows
+    // a character without one rather than no list at all
+    "UPDATE characters SET detail_json = '' WHERE length(CAST(detail_json AS BLOB)) > 1049;
+    CREATE TRIGGER characters_detail_json_insert BEFORE INSERT ON characters
+    WHEN length(CAST(NEW.detail_json AS BLOB)) > 1049
+    BEGIN
+        SELECT RAISE(ABORT, 'detail_json is longer than 1049 bytes');
+    END;
+    CREATE TRIGGER characters_detail_json_update BEFORE UPDATE OF detail_json ON characters
+    WHEN length(CAST(NEW.detail_json AS BLOB)) > 1049
+    BEGIN
+        SELECT RAISE(ABORT, 'detail_json is longer than 1049 bytes');
+    END;",
+];
+
+pub fn schema_version(conn: &Connection) -> Result<u32> {
+    Ok(conn.query_row("PRAGMA user_version", [], |row| row.get(0))?)
+}
+
+// characters sharing a name with an earlier one on their world, which migration 3 can't index.
+// there's no new name to give them that's sure to follow the name rules and not be taken, so
+// they're left for an admin
+fn duplicate_names(conn: &Connection) -> Result<Vec<String>> {
+    let mut stmt = conn.prepare(
+        "SELECT characters.id, characters.world_id, characters.name, MIN(first.id)
+        FROM characters
+        JOIN characters AS first
+            ON first.world_id = characters.world_id
+            AND first.name = characters.name COLLATE NOCASE
+            AND first.id < characters.id
+        GROUP BY characters.id
+        ORDER BY characters.id",
+    )?;
+    let duplicates = stmt
+        .query_map([], |row| {
+            Ok(format!(
+                "character {} ({} on world {}, taken first by character {})",
+                row.get::<_, u32>(0)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, u32>(1)?,
+                row.get::<_, u32>(3)?
+            ))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(duplicates)
+}
+
+pub fn migrate(conn: &mut Connection) -> Result<()> {
+    let version = schema_version(conn)?;
+    let supported = MIGRATIONS.len() as u32;
+    if version > supported {
+        return Err(StorageError::SchemaTooNew {
+            found: version,
+            supported,
+        });
+    }
+
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        let tx = conn.transaction()?;
+        if i == 2 {
+            let duplicates = duplicate_names(&tx)?;
+            if !duplicates.is_empty() {
+                return Err(StorageError::DuplicateNames(duplicates));
+            }
+        }
+        tx.execute_batch(migration)?;
+        tx.pragma_update(None, "user_version", i as u32 + 1)?;
+        tx.commit()?;
+    }
+
+    Ok(())
+}
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
@@ -0,0 +1,435 @@
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use std::{
+    path::Path,
+    sync::{Mutex, MutexGuard},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    migrations, Account, AccountRepository, Character, CharacterRepository, IdAllocator,
+    LoginQueueRepository, QueueEntry, Result, Retainer, RetainerRepository, ServiceAccount,
+    ServiceAccountRepository, SessionRepository, StorageError, CHARACTER_ID_BASE, CONTENT_ID_BASE,
+    LOBBY_ACTOR_ID_BASE, MAX_DETAIL_JSON_LEN,
+};
+
+fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("time went backwards")
+        .as_secs()
+}
+
+fn character_from_row(row: &Row) -> rusqlite::Result<Character> {
+    Ok(Character {
+        id: row.get(0)?,
+        content_id: row.get::<_, i64>(1)? as u64,
+        character_id: row.get(2)?,
+        world_id: row.get(3)?,
+        name: row.get(4)?,
+        detail_json: row.get(5)?,
+    })
+}
+
+// a single UPDATE is atomic even with other processes writing to the file, so two lobbies
+// can't both get the same number
+fn next_in_sequence(conn: &Connection, sequence: &str) -> Result<u64> {
+    Ok(conn.query_row(
+        "UPDATE id_sequences SET last = last + 1 WHERE name = ?1 RETURNING last",
+        params![sequence],
+        |row| row.get::<_, i64>(0),
+    )? as u64)
+}
+
+fn next_content_id(conn: &Connection) -> Result<u64> {
+    Ok(CONTENT_ID_BASE + next_in_sequence(conn, "content")?)
+}
+
+fn next_character_id(conn: &Connection) -> Result<u32> {
+    let next = next_in_sequence(conn, "character")?;
+    if next >= (LOBBY_ACTOR_ID_BASE - CHARACTER_ID_BASE) as u64 {
+        return Err(StorageError::IdsExhausted("character"));
+    }
+    Ok(CHARACTER_ID_BASE + next as u32)
+}
+
+// rusqlite connections aren't Sync, so every caller shares this one behind a lock.
+// nothing is cached on our side, so changes made by another process apply on the next query
+pub struct SqliteStorage {
+    conn: Mutex<Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStorage> {
+        SqliteStorage::from_connection(Connection::open(path)?)
+    }
+
+    // a fresh database that's gone when dropped, for tests and throwaway servers
+    pub fn open_in_memory() -> Result<SqliteStorage> {
+        SqliteStorage::from_connection(Connection::open_in_memory()?)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<SqliteStorage> {
+        conn.pragma_update(None, "foreign_keys", true)?;
+        // several lobbies can share one file, so wait for each other's writes instead of failing
+        conn.busy_timeout(std::time::Duration::from_secs(5))?;
+        migrations::migrate(&mut conn)?;
+
+        Ok(SqliteStorage {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    pub fn schema_version(&self) -> Result<u32> {
+        migrations::schema_version(&self.conn())
+    }
+
+    pub fn latest_schema_version() -> u32 {
+        migrations::latest_version()
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().expect("database lock poisoned")
+    }
+}
+
+impl IdAllocator for SqliteStorage {
+    fn allocate_content_id(&self) -> Result<u64> {
+        next_content_id(&self.conn())
+    }
+
+    fn allocate_character_id(&self) -> Result<u32> {
+        next_character_id(&self.conn())
+    }
+
+    fn allocate_lobby_actor_id(&self) -> Result<u32> {
+        let next = next_in_sequence(&self.conn(), "lobby_actor")?;
+        if next > (u32::MAX - LOBBY_ACTOR_ID_BASE) as u64 {
+            return Err(StorageError::IdsExhausted("lobby actor"));
+        }
+        Ok(LOBBY_ACTOR_ID_BASE + next as u32)
+    }
+}
+
+impl AccountRepository for SqliteStorage {
+    fn add_account(&self, username: &str) -> Result<u32> {
+        let conn = self.conn();
+        conn.execute("INSERT INTO accounts (username) VALUES (?1)", params![username])?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+
+    fn account(&self, id: u32) -> Result<Option<Account>> {
+        Ok(self
+            .conn()
+            .query_row(
+                "SELECT id, username FROM accounts WHERE id = ?1",
+                params![id],
+                |row| {
+                    Ok(Account {
+                        id: row.get(0)?,
+                        username: row.get(1)?,
+                    })
+                },
+            )
+            .optional()?)
+    }
+}
+
+impl SessionRepository for SqliteStorage {
+    fn add_session(&self, account_id: u32, session_id: &str) -> Result<()> {
+        self.conn().execute(
+            "INSERT OR REPLACE INTO sessions (session_id, account_id) VALUES (?1, ?2)",
+            params![session_id, account_id],
+        )?;
+        Ok(())
+    }
+
+    fn remove_session(&self, session_id: &str) -> Result<()> {
+        self.conn().execute(
+            "DELETE FROM sessions WHERE session_id = ?1",
+            params![session_id],
+        )?;
+        Ok(())
+    }
+
+    fn account_for_session(&self, session_id: &str) -> Result<Option<u32>> {
+        Ok(self
+            .conn()
+            .query_row(
+                "SELECT account_id FROM sessions WHERE session_id = ?1",
+                params![session_id],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+}
+
+impl ServiceAccountRepository for SqliteStorage {
+    fn add_service_account(&self, account_id: u32, name: &str) -> Result<u32> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO service_accounts (account_id, name, position)
+             SELECT ?1, ?2, COALESCE(MAX(position) + 1, 0)
+             FROM service_accounts WHERE account_id = ?1",
+            params![account_id, name],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+
+    fn set_service_account_enabled(&self, id: u32, enabled: bool) -> Result<bool> {
+        let changed = self.conn().execute(
+            "UPDATE service_accounts SET enabled = ?2 WHERE id = ?1",
+            params![id, enabled],
+        )?;
+        Ok(changed > 0)
+    }
+
+    fn service_accounts(&self, account_id: u32) -> Result<Vec<ServiceAccount>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, enabled FROM service_accounts
+             WHERE account_id = ?1 ORDER BY position, id",
+        )?;
+
+        let rows = stmt.query_map(params![account_id], |row| {
+            Ok(ServiceAccount {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                enabled: row.get(2)?,
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+impl CharacterRepository for SqliteStorage {
+    fn add_character(&self, account_id: u32, world_id: u16, name: &str) -> Result<Character> {
+        let mut conn = self.conn();
+        let tx = conn.transaction()?;
+        let content_id = next_content_id(&tx)?;
+        let character_id = next_character_id(&tx)?;
+        tx.execute(
+            "INSERT INTO characters (content_id, character_id, account_id, world_id, name)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![content_id as i64, character_id, account_id, world_id, name],
+        )?;
+
+        let id = tx.last_insert_rowid() as u32;
+        tx.commit()?;
+
+        Ok(Character {
+            id,
+            content_id,
+            character_id,
+            world_id,
+            name: name.to_string(),
+            detail_json: String::new(),
+        })
+    }
+
+    fn characters(&self, account_id: u32) -> Result<Vec<Character>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, content_id, character_id, world_id, name, detail_json FROM characters
+             WHERE account_id = ?1 ORDER BY id",
+        )?;
+
+        let rows = stmt.query_map(params![account_id], character_from_row)?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn character_by_content_id(&self, content_id: u64) -> Result<Option<Character>> {
+        Ok(self
+            .conn()
+            .query_row(
+                "SELECT id, content_id, character_id, world_id, name, detail_json FROM characters
+                 WHERE content_id = ?1",
+                params![content_id as i64],
+                character_from_row,
+            )
+            .optional()?)
+    }
+
+    fn set_character_detail_json(&self, id: u32, detail_json: &str) -> Result<bool> {
+        if detail_json.len() > MAX_DETAIL_JSON_LEN {
+            return Err(StorageError::DetailJsonTooLong(detail_json.len()));
+        }
+
+        let updated = self.conn().execute(
+            "UPDATE characters SET detail_json = ?1 WHERE id = ?2",
+            params![detail_json, id],
+        )?;
+        Ok(updated > 0)
+    }
+
+    fn character_name_taken(&self, world_id: u16, name: &str) -> Result<bool> {
+        Ok(self.conn().query_row(
+            "SELECT EXISTS (SELECT 1 FROM characters
+             WHERE world_id = ?1 AND name = ?2 COLLATE NOCASE)",
+            params![world_id, name],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn delete_character(&self, id: u32) -> Result<()> {
+        self.conn()
+            .execute("DELETE FROM characters WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+}
+
+impl RetainerRepository for SqliteStorage {
+    fn add_retainer(&self, character_id: u32, name: &str) -> Result<u32> {
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO retainers (character_id, name) VALUES (?1, ?2)",
+            params![character_id, name],
+        )?;
+        Ok(conn.last_insert_rowid() as u32)
+    }
+
+    fn remove_retainer(&self, id: u32) -> Result<bool> {
+        let changed = self
+            .conn()
+            .execute("DELETE FROM retainers WHERE id = ?1", params![id])?;
+        Ok(changed > 0)
+    }
+
+    fn retainer_count(&self, character_id: u32) -> Result<u32> {
+        Ok(self.conn().query_row(
+            "SELECT COUNT(*) FROM retainers WHERE character_id = ?1",
+            params![character_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn retainers(&self, account_id: u32) -> Result<Vec<Retainer>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT retainers.id, characters.content_id, retainers.name,
+                    retainers.class_job, retainers.level
+             FROM retainers JOIN characters ON characters.id = retainers.character_id
+             WHERE characters.account_id = ?1 ORDER BY characters.id, retainers.id",
+        )?;
+
+        let rows = stmt.query_map(params![account_id], |row| {
+            Ok(Retainer {
+                id: row.get(0)?,
+                owner_content_id: row.get::<_, i64>(1)? as u64,
+                name: row.get(2)?,
+                class_job: row.get(3)?,
+                level: row.get(4)?,
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+}
+
+impl LoginQueueRepository for SqliteStorage {
+    fn join_queue(&self, world_id: u16, content_id: u64) -> Result<u64> {
+        let now = unix_time();
+        let conn = self.conn();
+        conn.execute(
+            "INSERT INTO login_queue (world_id, content_id, joined_at, last_seen)
+             VALUES (?1, ?2, ?3, ?3)",
+            params![world_id, content_id as i64, now as i64],
+        )?;
+        Ok(conn.last_insert_rowid() as u64)
+    }
+
+    fn queue_position(&self, ticket: u64, stale_after: u64) -> Result<Option<u32>> {
+        let now = unix_time();
+        let conn = self.conn();
+        conn.execute(
+            "DELETE FROM login_queue WHERE last_seen < ?1",
+            params![now.saturating_sub(stale_after) as i64],
+        )?;
+
+        let changed = conn.execute(
+            "UPDATE login_queue SET last_seen = ?2 WHERE ticket = ?1",
+            params![ticket as i64, now as i64],
+        )?;
+        if changed == 0 {
+            return Ok(None);
+        }
+
+        let position = conn.query_row(
+            "SELECT COUNT(*) FROM login_queue
+             WHERE world_id = (SELECT world_id FROM login_queue WHERE ticket = ?1)
+             AND ticket < ?1",
+            params![ticket as i64],
+            |row| row.get(0),
+        )?;
+        Ok(Some(position))
+    }
+
+    fn queue_len(&self, world_id: u16) -> Result<u32> {
+        Ok(self.conn().query_row(
+            "SELECT COUNT(*) FROM login_queue WHERE world_id = ?1",
+            params![world_id],
+            |row| row.get(0),
+        )?)
+    }
+
+    fn leave_queue(&self, ticket: u64) -> Result<()> {
+        self.conn().execute(
+            "DELETE FROM login_queue WHERE ticket = ?1",
+            params![ticket as i64],
+        )?;
+        Ok(())
+    }
+
+    fn record_admission(&self, world_id: u16) -> Result<()> {
+        self.conn().execute(
+            "INSERT INTO queue_admissions (world_id, admitted_at) VALUES (?1, ?2)",
+            params![world_id, unix_time() as i64],
+        )?;
+        Ok(())
+    }
+
+    fn admission_interval(&self, world_id: u16) -> Result<Option<u64>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT admitted_at FROM queue_admissions WHERE world_id = ?1
+             ORDER BY admitted_at DESC LIMIT 10",
+        )?;
+
+        let times = stmt
+            .query_map(params![world_id], |row| row.get::<_, i64>(0))?
+            .collect::<rusqlite::Result<Vec<i64>>>()?;
+
+        if times.len() < 2 {
+            return Ok(None);
+        }
+
+        let span = (times[0] - times[times.len() - 1]) as u64;
+        Ok(Some(span / (times.len() as u64 - 1)))
+    }
+
+    fn queue(&self, world_id: Option<u16>) -> Result<Vec<QueueEntry>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT ticket, world_id, content_id, joined_at FROM login_queue
+             WHERE ?1 IS NULL OR world_id = ?1 ORDER BY world_id, ticket",
+        )?;
+
+        let rows = stmt.query_map(params![world_id], |row| {
+            Ok(QueueEntry {
+                ticket: row.get::<_, i64>(0)? as u64,
+                world_id: row.get(1)?,
+                content_id: row.get::<_, i64>(2)? as u64,
+                joined_at: row.get::<_, i64>(3)? as u64,
+            })
+        })?;
+
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    fn clear_queue(&self, world_id: Option<u16>) -> Result<usize> {
+        Ok(self.conn().execute(
+            "DELETE FROM login_queue WHERE ?1 IS NULL OR world_id = ?1",
+            params![world_id],
+        )?)
+    }
+}
@@ -0,0 +1,287 @@
+use storage::{
+    AccountRepository, CharacterRepository, IdAllocator, LoginQueueRepository, RetainerRepository,
+    ServiceAccountRepository, SessionRepository, SqliteStorage, StorageError, CHARACTER_ID_BASE,
+    CONTENT_ID_BASE, LOBBY_ACTOR_ID_BASE, MAX_DETAIL_JSON_LEN,
+};
+
+fn storage() -> SqliteStorage {
+    SqliteStorage::open_in_memory().expect("failed to open in-memory storage")
+}
+
+#[test]
+fn migrates_to_latest_schema() {
+    let storage = storage();
+    assert_eq!(
+        storage.schema_version().unwrap(),
+        SqliteStorage::latest_schema_version()
+    );
+}
+
+#[test]
+fn reopening_a_migrated_database_keeps_its_data() {
+    let path = std::env::temp_dir().join(format!("storage-test-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let account_id = {
+        let storage = SqliteStorage::open(&path).unwrap();
+        storage.add_account("reopen").unwrap()
+    };
+
+    let storage = SqliteStorage::open(&path).unwrap();
+    assert_eq!(
+        storage.account(account_id).unwrap().unwrap().username,
+        "reopen"
+    );
+    drop(storage);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn upgrading_cleans_up_characters_from_older_databases() {
+    let path = std::env::temp_dir().join(format!("storage-upgrade-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    // a database from before migrations, back when nothing stopped two lobbies picking a name
+    // or a description too long for the character list
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE accounts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                username TEXT NOT NULL UNIQUE
+            );
+            CREATE TABLE characters (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                content_id INTEGER NOT NULL UNIQUE,
+                account_id INTEGER NOT NULL REFERENCES accounts(id),
+                world_id INTEGER NOT NULL,
+                name TEXT NOT NULL,
+                detail_json TEXT NOT NULL DEFAULT ''
+            );
+            INSERT INTO accounts (username) VALUES ('old');
+            INSERT INTO characters (content_id, account_id, world_id, name) VALUES
+                (1, 1, 1, 'Same Name'),
+                (2, 1, 1, 'same name'),
+                (3, 1, 2, 'Same Name'),
+                (4, 1, 1, 'Other Name'),
+                (5, 1, 1, 'SAME NAME');
+            UPDATE characters SET detail_json = printf('%.*c', 1050, 'x') WHERE id = 4;",
+        )
+        .unwrap();
+    }
+
+    // there's no renaming them to something sure to be free, so it's left to an admin
+    match SqliteStorage::open(&path) {
+        Err(StorageError::DuplicateNames(duplicates)) => assert_eq!(
+            duplicates,
+            [
+                "character 2 (same name on world 1, taken first by character 1)",
+                "character 5 (SAME NAME on world 1, taken first by character 1)",
+            ]
+        ),
+        _ => panic!("expected the duplicate names to stop the upgrade"),
+    }
+
+    // once they've sorted it out, the upgrade carries on from where it stopped
+    {
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(
+            "UPDATE characters SET name = 'Another Name' WHERE id = 2;
+            DELETE FROM characters WHERE id = 5;",
+        )
+        .unwrap();
+    }
+    let storage = SqliteStorage::open(&path).unwrap();
+    let names: Vec<_> = storage
+        .characters(1)
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.world_id, c.name))
+        .collect();
+    assert_eq!(
+        names,
+        [
+            (1, "Same Name".to_string()),
+            (1, "Another Name".to_string()),
+            (2, "Same Name".to_string()),
+            (1, "Other Name".to_string()),
+        ]
+    );
+    assert!(storage.add_character(1, 1, "SAME NAME").is_err());
+    // a description too long for the character list is dropped rather than breaking it
+    assert_eq!(storage.characters(1).unwrap()[3].detail_json, "");
+
+    // and writing one behind storage's back doesn't get around the limit
+    let conn = rusqlite::Connection::open(&path).unwrap();
+    assert!(conn
+        .execute(
+            "UPDATE characters SET detail_json = printf('%.*c', 1050, 'x') WHERE id = 1",
+            [],
+        )
+        .is_err());
+
+    drop(storage);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn sessions_resolve_to_their_account() {
+    let storage = storage();
+    let account_id = storage.add_account("alice").unwrap();
+
+    storage.add_session(account_id, "abcdef").unwrap();
+    assert_eq!(
+        storage.account_for_session("abcdef").unwrap(),
+        Some(account_id)
+    );
+    assert_eq!(storage.account_for_session("nope").unwrap(), None);
+
+    storage.remove_session("abcdef").unwrap();
+    assert_eq!(storage.account_for_session("abcdef").unwrap(), None);
+}
+
+#[test]
+fn sessions_need_an_account() {
+    let storage = storage();
+    assert!(storage.add_session(42, "orphan").is_err());
+}
+
+#[test]
+fn service_accounts_keep_insertion_order() {
+    let storage = storage();
+    let account_id = storage.add_account("bob").unwrap();
+    let other_id = storage.add_account("carol").unwrap();
+
+    let first = storage.add_service_account(account_id, "first").unwrap();
+    storage.add_service_account(other_id, "elsewhere").unwrap();
+    let second = storage.add_service_account(account_id, "second").unwrap();
+
+    assert!(storage.set_service_account_enabled(first, false).unwrap());
+    assert!(!storage.set_service_account_enabled(9999, true).unwrap());
+
+    let accounts = storage.service_accounts(account_id).unwrap();
+    let summary: Vec<_> = accounts
+        .iter()
+        .map(|a| (a.id, a.name.as_str(), a.enabled))
+        .collect();
+    assert_eq!(summary, [(first, "first", false), (second, "second", true)]);
+}
+
+#[test]
+fn characters_and_retainers() {
+    let storage = storage();
+    let account_id = storage.add_account("dave").unwrap();
+
+    let character = storage
+        .add_character(account_id, 1, "Test Character")
+        .unwrap();
+    assert_eq!(character.name, "Test Character");
+
+    let characters = storage.characters(account_id).unwrap();
+    assert_eq!(characters.len(), 1);
+    assert_eq!(characters[0].content_id, character.content_id);
+    assert_eq!(characters[0].world_id, 1);
+
+    let retainer = storage.add_retainer(character.id, "Helper").unwrap();
+    assert_eq!(storage.retainer_count(character.id).unwrap(), 1);
+
+    let retainers = storage.retainers(account_id).unwrap();
+    assert_eq!(retainers.len(), 1);
+    assert_eq!(retainers[0].owner_content_id, character.content_id);
+    assert_eq!(retainers[0].name, "Helper");
+
+    // retainers have to be let go before their employer can be deleted
+    assert!(storage.delete_character(character.id).is_err());
+    assert!(storage.remove_retainer(retainer).unwrap());
+    assert!(!storage.remove_retainer(retainer).unwrap());
+    storage.delete_character(character.id).unwrap();
+    assert!(storage.characters(account_id).unwrap().is_empty());
+}
+
+#[test]
+fn character_descriptions_have_to_fit_the_list() {
+    let storage = storage();
+    let account_id = storage.add_account("erin").unwrap();
+    let character = storage
+        .add_character(account_id, 1, "Test Character")
+        .unwrap();
+
+    let fits = "x".repeat(MAX_DETAIL_JSON_LEN);
+    assert!(storage
+        .set_character_detail_json(character.id, &fits)
+        .unwrap());
+    assert_eq!(storage.characters(account_id).unwrap()[0].detail_json, fits);
+    assert!(!storage.set_character_detail_json(9999, "{}").unwrap());
+
+    assert!(matches!(
+        storage.set_character_detail_json(character.id, &format!("{}x", fits)),
+        Err(StorageError::DetailJsonTooLong(1050))
+    ));
+    // counted in bytes, the way the client's buffer is
+    assert!(storage
+        .set_character_detail_json(character.id, &"é".repeat(525))
+        .is_err());
+}
+
+#[test]
+fn queue_positions_are_per_world() {
+    let storage = storage();
+
+    let first = storage.join_queue(1, 100).unwrap();
+    let other_world = storage.join_queue(2, 101).unwrap();
+    let second = storage.join_queue(1, 102).unwrap();
+
+    assert_eq!(storage.queue_position(first, 60).unwrap(), Some(0));
+    assert_eq!(storage.queue_position(second, 60).unwrap(), Some(1));
+    assert_eq!(storage.queue_position(other_world, 60).unwrap(), Some(0));
+    assert_eq!(storage.queue_len(1).unwrap(), 2);
+
+    storage.leave_queue(first).unwrap();
+    assert_eq!(storage.queue_position(first, 60).unwrap(), None);
+    assert_eq!(storage.queue_position(second, 60).unwrap(), Some(0));
+
+    assert_eq!(storage.queue(None).unwrap().len(), 2);
+    assert_eq!(storage.clear_queue(Some(2)).unwrap(), 1);
+    assert_eq!(storage.queue(None).unwrap().len(), 1);
+}
+
+#[test]
+fn admission_interval_needs_history() {
+    let storage = storage();
+    assert_eq!(storage.admission_interval(1).unwrap(), None);
+
+    storage.record_admission(1).unwrap();
+    assert_eq!(storage.admission_interval(1).unwrap(), None);
+
+    storage.record_admission(1).unwrap();
+    assert!(storage.admission_interval(1).unwrap().is_some());
+}
+
+#[test]
+fn ids_are_unique_and_in_range() {
+    let storage = storage();
+    let account_id = storage.add_account("erin").unwrap();
+
+    let first = storage.add_character(account_id, 1, "First One").unwrap();
+    let second = storage.add_character(account_id, 1, "Second One").unwrap();
+    assert_ne!(first.content_id, second.content_id);
+    assert_ne!(first.character_id, second.character_id);
+    assert!(first.content_id > CONTENT_ID_BASE);
+    assert!(first.character_id > CHARACTER_ID_BASE);
+
+    // ids aren't reused once their character is gone
+    storage.delete_character(second.id).unwrap();
+    let third = storage.add_character(account_id, 1, "Third One").unwrap();
+    assert!(third.content_id > second.content_id);
+    assert!(third.character_id > second.character_id);
+
+    let found = storage
+        .character_by_content_id(third.content_id)
+        .unwrap()
+        .unwrap();
+    assert_eq!(found.character_id, third.character_id);
+
+    let lobby_actor = storage.allocate_lobby_actor_id().unwrap();
+    assert!(lobby_actor > LOBBY_ACTOR_ID_BASE);
+    assert_ne!(storage.allocate_lobby_actor_id().unwrap(), lobby_actor);
+}